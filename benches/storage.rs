@@ -0,0 +1,69 @@
+//! Throughput of the SQLite storage, writing every message in its own transaction as an
+//! autocommit insert does, against writing them in batches.
+//!
+//! Run with `cargo bench --bench storage`.
+
+use bft_test::whitebox::{
+    collection::{
+        storage::{Direction, MsgMeta, SqliteStorage, Storage},
+        util::Msg,
+    },
+    Vote, VoteType,
+};
+use std::time::{Duration, Instant};
+
+const MESSAGES: u64 = 5000;
+
+fn votes() -> Vec<Msg> {
+    (0..MESSAGES)
+        .map(|i| {
+            Msg::Vote(Vote {
+                height: i / 100,
+                round: 0,
+                vote_type: VoteType::Prevote,
+                proposal: vec![1; 32],
+                voter: vec![(i % 4) as u8; 20],
+                signature: vec![2; 64],
+            })
+        })
+        .collect()
+}
+
+fn bench(name: &str, flush_every: bool) -> Duration {
+    let path = std::env::temp_dir().join(format!("bft_bench_{}_{}.db", name, std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    let storage = SqliteStorage::new(&path).unwrap();
+    let meta = MsgMeta {
+        timestamp: time::get_time(),
+        direction: Direction::Send,
+        step: None,
+        suite: None,
+    };
+
+    let start = Instant::now();
+    for msg in votes().into_iter() {
+        storage.insert(msg, &meta).unwrap();
+        if flush_every {
+            storage.flush().unwrap();
+        }
+    }
+    storage.flush().unwrap();
+    let elapsed = start.elapsed();
+
+    drop(storage);
+    for suffix in ["", "-wal", "-shm"].iter() {
+        let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+    }
+    elapsed
+}
+
+fn main() {
+    for (name, flush_every) in [("per message", true), ("batched", false)].iter() {
+        let elapsed = bench(&name.replace(' ', "_"), *flush_every);
+        let rate = MESSAGES as f64 / elapsed.as_secs_f64();
+        println!(
+            "{:>12}: {} messages in {:?}, {:.0} msg/s",
+            name, MESSAGES, elapsed, rate
+        );
+    }
+}
@@ -10,5 +10,8 @@ pub use crate::whitebox::correctness::test_case;
 pub use crossbeam;
 /// Blackbox testing module.
 pub mod blackbox;
+/// Reference implementation to test the framework.
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
 /// WhiteBox testing module.
 pub mod whitebox;
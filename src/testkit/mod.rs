@@ -0,0 +1,70 @@
+/// Mutations of the reference node and the suites catching them.
+pub mod mutation;
+/// A reference Tendermint node.
+pub mod node;
+
+pub use self::mutation::Mutation;
+pub use self::node::ReferenceNode;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::whitebox::{
+        actuator::Actuator,
+        correctness::proposer::RoundRobin,
+        crypto::{Crypto, Ed25519},
+        scheduler::Delivery,
+    };
+
+    fn authority_list() -> Vec<Vec<u8>> {
+        (0..4).map(|i| vec![i]).collect()
+    }
+
+    #[test]
+    fn test_reference_node() {
+        let node = ReferenceNode::new(authority_list());
+        let mut actuator = Actuator::new(node, 0, 0, authority_list(), ":memory:").unwrap();
+        actuator.all_test().unwrap();
+        let report = actuator
+            .check_proposer(&RoundRobin::new(4), 0..20, 0..10)
+            .unwrap();
+        assert_eq!(report.distribution, vec![50; 4]);
+    }
+
+    #[test]
+    fn test_reference_node_with_crypto() {
+        let crypto = Ed25519;
+        let (privkeys, authority_list): (Vec<_>, Vec<_>) = (0..4)
+            .map(|_| {
+                let (privkey, pubkey) = crypto.gen_keypair();
+                (privkey, crypto.address(&pubkey))
+            })
+            .unzip();
+        let node = ReferenceNode::with_crypto(
+            authority_list.clone(),
+            Box::new(crypto),
+            privkeys[0].clone(),
+        );
+        let mut actuator = Actuator::new(node, 0, 0, authority_list, ":memory:").unwrap();
+        actuator
+            .set_crypto(Box::new(crypto), privkeys[1..].to_vec())
+            .unwrap();
+        actuator.all_test().unwrap();
+    }
+
+    #[test]
+    fn test_reference_node_with_delivery() {
+        let node = ReferenceNode::new(authority_list());
+        let mut actuator = Actuator::new(node, 0, 0, authority_list(), ":memory:").unwrap();
+        // a delayed vote may miss the quorum the suites expect, so only duplicate and reorder
+        actuator
+            .set_delivery(Delivery {
+                seed: 7,
+                duplicate: 0.2,
+                delay: 0.0,
+                reorder: true,
+            })
+            .unwrap();
+        actuator.all_test().unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+use crate::testkit::ReferenceNode;
+use crate::whitebox::{
+    actuator::Actuator, correctness::proposer::RoundRobin, correctness::test_case::all_cases,
+    error::BftError, BftResult,
+};
+
+use std::fmt;
+
+/// A deliberate bug of the reference node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mutation {
+    /// Prevote and propose the proposal of the round even if locked on another one.
+    IgnoreLock,
+    /// Precommit for its own prevote at once, without waiting for +2/3 prevotes.
+    PrecommitWithoutQuorum,
+    /// Commit with more than 1/3 precommits instead of more than 2/3.
+    CommitEarly,
+    /// Send a second prevote for nil after every prevote.
+    DoubleVote,
+    /// Elect the next authority of the right proposer.
+    WrongProposer,
+    /// Lose the lock on every new round, as if restarted without persisting it.
+    ForgetLock,
+    /// Commit with only one of the precommits as the proof.
+    ShortProof,
+    /// Commit every height twice.
+    RepeatCommit,
+    /// Commit every height again with another result.
+    ChangeCommit,
+    /// Commit at the next height instead of the current one.
+    CommitNextHeight,
+}
+
+impl Mutation {
+    /// A function to get all the mutations.
+    pub fn all() -> Vec<Mutation> {
+        vec![
+            Mutation::IgnoreLock,
+            Mutation::PrecommitWithoutQuorum,
+            Mutation::CommitEarly,
+            Mutation::DoubleVote,
+            Mutation::WrongProposer,
+            Mutation::ForgetLock,
+            Mutation::ShortProof,
+            Mutation::RepeatCommit,
+            Mutation::ChangeCommit,
+            Mutation::CommitNextHeight,
+        ]
+    }
+}
+
+/// Name of the proposer check in a detection report.
+pub const PROPOSER_CHECK: &str = "check proposer";
+
+/// Result of every suite against a mutated reference node.
+#[derive(Debug)]
+pub struct Detection {
+    /// The mutation under test.
+    pub mutation: Mutation,
+    /// Suite name and the result, sorted by the suite name. The proposer check is reported as
+    /// `PROPOSER_CHECK`.
+    pub results: Vec<(String, BftResult<()>)>,
+}
+
+impl Detection {
+    /// A function to get the suites which catch the mutation, with the error they return.
+    pub fn caught_by(&self) -> Vec<(&str, &BftError)> {
+        self.results
+            .iter()
+            .filter_map(|(name, res)| res.as_ref().err().map(|e| (name.as_str(), e)))
+            .collect()
+    }
+
+    /// A function to check whether any suite catches the mutation.
+    pub fn is_detected(&self) -> bool {
+        self.results.iter().any(|(_, res)| res.is_err())
+    }
+}
+
+impl fmt::Display for Detection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let caught = self.caught_by();
+        writeln!(
+            f,
+            "{:?}: caught by {}/{} suites",
+            self.mutation,
+            caught.len(),
+            self.results.len()
+        )?;
+        for (name, err) in caught.iter() {
+            writeln!(f, "    {}: {}", name, err.root())?;
+        }
+        Ok(())
+    }
+}
+
+/// A function to run every suite and the proposer check against a reference node with the
+/// mutation, each with a new node and actuator.
+pub fn detect(mutation: Mutation) -> Detection {
+    let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+    let new_actuator = || {
+        let node = ReferenceNode::with_mutation(authority_list.clone(), mutation);
+        Actuator::new(node, 0, 0, authority_list.clone(), ":memory:").unwrap()
+    };
+
+    let mut results: Vec<(String, BftResult<()>)> = all_cases()
+        .into_iter()
+        .map(|(name, cases)| {
+            let res = new_actuator().proc_test(cases);
+            (name, res)
+        })
+        .collect();
+    let res = new_actuator()
+        .check_proposer(&RoundRobin::new(authority_list.len()), 0..20, 0..10)
+        .map(|_| ());
+    results.push((PROPOSER_CHECK.to_string(), res));
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    Detection { mutation, results }
+}
+
+/// A function to run `detect` for all the mutations.
+pub fn detection_matrix() -> Vec<Detection> {
+    Mutation::all().into_iter().map(detect).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expected(mutation: Mutation, err: &BftError) -> bool {
+        let err = err.root();
+        match mutation {
+            Mutation::IgnoreLock | Mutation::ForgetLock => matches!(
+                err,
+                BftError::IllegalVote(_) | BftError::IllegalProposal(_, _)
+            ),
+            Mutation::PrecommitWithoutQuorum => matches!(err, BftError::ShouldNotPrecommit(_, _)),
+            Mutation::CommitEarly => matches!(
+                err,
+                BftError::CommitInvalid(_)
+                    | BftError::CommitIncorrect(_)
+                    | BftError::IllegalCommitProof(_)
+            ),
+            Mutation::DoubleVote => matches!(err, BftError::IllegalVote(_)),
+            Mutation::WrongProposer => matches!(err, BftError::ProposerDiff(_, _)),
+            Mutation::ShortProof => matches!(err, BftError::IllegalCommitProof(_)),
+            Mutation::RepeatCommit => matches!(err, BftError::MultipleCommit(_)),
+            Mutation::ChangeCommit => matches!(err, BftError::CommitDiff(_)),
+            Mutation::CommitNextHeight => matches!(err, BftError::MislaidCommit(_)),
+        }
+    }
+
+    #[test]
+    fn test_detection_matrix() {
+        for detection in detection_matrix().iter() {
+            println!("{}", detection);
+            assert!(
+                detection
+                    .caught_by()
+                    .iter()
+                    .any(|(_, err)| expected(detection.mutation, err)),
+                "{:?} is not caught as expected",
+                detection.mutation
+            );
+        }
+    }
+}
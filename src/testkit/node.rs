@@ -0,0 +1,434 @@
+use crate::testkit::mutation::Mutation;
+use crate::whitebox::{
+    correctness::proposer::{ProposerElection, RoundRobin},
+    crypto::{proposal_hash, vote_hash, Crypto},
+    *,
+};
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+/// A reference Tendermint node to test the framework itself.
+///
+/// The node is the first authority of the authority list and elects proposers by
+/// `RoundRobin`. It handles every message as soon as it is sent, and fires its timeouts when
+/// the actuator waits for it: `recv` with nothing to send times out the current step, and
+/// `try_get_commit` without a commit times out the precommit step. A valid proposal of a later
+/// round in the height, or a feed when the node is not the proposer of its round, makes the
+/// node skip to that round, as if the timeouts between had passed.
+pub struct ReferenceNode(RefCell<Node>);
+
+impl ReferenceNode {
+    /// A function to create a new reference node.
+    pub fn new(authority_list: Vec<Vec<u8>>) -> Self {
+        ReferenceNode(RefCell::new(Node::new(authority_list, None)))
+    }
+
+    /// A function to create a new reference node with a deliberate bug.
+    pub fn with_mutation(authority_list: Vec<Vec<u8>>, mutation: Mutation) -> Self {
+        let mut node = Node::new(authority_list, None);
+        node.mutation = Some(mutation);
+        ReferenceNode(RefCell::new(node))
+    }
+
+    /// A function to create a new reference node which signs its messages and verifies the
+    /// signatures of the others.
+    pub fn with_crypto(
+        authority_list: Vec<Vec<u8>>,
+        crypto: Box<dyn Crypto>,
+        privkey: Vec<u8>,
+    ) -> Self {
+        ReferenceNode(RefCell::new(Node::new(
+            authority_list,
+            Some((crypto, privkey)),
+        )))
+    }
+}
+
+impl Support for ReferenceNode {
+    fn send(&self, msg: FrameSend) {
+        self.0.borrow_mut().handle(msg);
+    }
+
+    fn recv(&self) -> FrameRecv {
+        self.0.borrow_mut().recv()
+    }
+
+    fn try_get_commit(&self) -> Option<Commit> {
+        self.0.borrow_mut().try_get_commit()
+    }
+
+    fn stop(&self) {}
+
+    fn cal_proposer(&self, height: u64, round: u64) -> usize {
+        self.0.borrow().proposer(height, round)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    Propose,
+    Prevote,
+    Precommit,
+}
+
+struct Lock {
+    round: u64,
+    proposal: Vec<u8>,
+    votes: Vec<Vote>,
+}
+
+struct Node {
+    mutation: Option<Mutation>,
+    crypto: Option<(Box<dyn Crypto>, Vec<u8>)>,
+    authority_list: Vec<Vec<u8>>,
+    height: u64,
+    round: u64,
+    step: Step,
+    feed: Option<Vec<u8>>,
+    lock: Option<Lock>,
+    // round -> proposal
+    proposals: HashMap<u64, Proposal>,
+    // (round, vote type) -> votes of different voters
+    votes: HashMap<(u64, VoteType), Vec<Vote>>,
+    outbox: VecDeque<FrameRecv>,
+    commits: VecDeque<Commit>,
+}
+
+impl Node {
+    fn new(authority_list: Vec<Vec<u8>>, crypto: Option<(Box<dyn Crypto>, Vec<u8>)>) -> Self {
+        Node {
+            mutation: None,
+            crypto,
+            authority_list,
+            height: 0,
+            round: 0,
+            step: Step::Propose,
+            feed: None,
+            lock: None,
+            proposals: HashMap::new(),
+            votes: HashMap::new(),
+            outbox: VecDeque::new(),
+            commits: VecDeque::new(),
+        }
+    }
+
+    fn address(&self) -> &Vec<u8> {
+        &self.authority_list[0]
+    }
+
+    fn is_mutated(&self, mutation: Mutation) -> bool {
+        self.mutation == Some(mutation)
+    }
+
+    fn proposer(&self, height: u64, round: u64) -> usize {
+        let n = self.authority_list.len();
+        let proposer = RoundRobin::new(n).proposer(height, round);
+        if self.is_mutated(Mutation::WrongProposer) {
+            return (proposer + 1) % n;
+        }
+        proposer
+    }
+
+    fn is_above_threshold(&self, num: usize) -> bool {
+        num * 3 > self.authority_list.len() * 2
+    }
+
+    fn handle(&mut self, msg: FrameSend) {
+        match msg {
+            FrameSend::Status(s) => {
+                self.authority_list = s.authority_list;
+                if s.height > self.height {
+                    self.goto_height(s.height);
+                }
+            }
+            FrameSend::Feed(f) => {
+                // a duplicated feed changes nothing
+                if f.height == self.height && self.feed.as_ref() != Some(&f.proposal) {
+                    self.feed = Some(f.proposal);
+                    self.handle_feed();
+                }
+            }
+            FrameSend::Proposal(p) => self.handle_proposal(p),
+            FrameSend::Vote(v) => self.handle_vote(v),
+        }
+    }
+
+    fn handle_feed(&mut self) {
+        // skip to the first round to propose
+        let mut round = self.round;
+        if self.step != Step::Propose {
+            round += 1;
+        }
+        while self.proposer(self.height, round) != 0 {
+            round += 1;
+        }
+        if round != self.round {
+            self.goto_round(round);
+        }
+        if self.step == Step::Propose && !self.proposals.contains_key(&self.round) {
+            self.propose();
+        }
+    }
+
+    fn handle_proposal(&mut self, proposal: Proposal) {
+        let proposer = self.proposer(proposal.height, proposal.round);
+        if proposal.height != self.height
+            || proposal.round < self.round
+            || proposer == 0
+            || proposal.proposer != self.authority_list[proposer]
+            || self.proposals.contains_key(&proposal.round)
+            || !self.verify(
+                &proposal_hash(&proposal),
+                &proposal.signature,
+                &proposal.proposer,
+            )
+        {
+            return;
+        }
+        let round = proposal.round;
+        self.proposals.insert(round, proposal);
+        if round > self.round {
+            self.goto_round(round);
+        } else if self.step == Step::Propose {
+            self.prevote();
+        }
+    }
+
+    fn handle_vote(&mut self, vote: Vote) {
+        if vote.height != self.height
+            || &vote.voter == self.address()
+            || !self.authority_list.contains(&vote.voter)
+            || !self.verify(&vote_hash(&vote), &vote.signature, &vote.voter)
+        {
+            return;
+        }
+        let round = vote.round;
+        let vote_type = vote.vote_type.clone();
+        let votes = self.votes.entry((round, vote_type.clone())).or_default();
+        if votes.iter().any(|v| v.voter == vote.voter) {
+            return;
+        }
+        votes.push(vote);
+
+        match vote_type {
+            VoteType::Prevote if round == self.round => self.check_prevote(),
+            VoteType::Precommit => self.check_precommit(round),
+            _ => (),
+        }
+    }
+
+    fn propose(&mut self) {
+        let lock = self
+            .lock
+            .as_ref()
+            .filter(|_| !self.is_mutated(Mutation::IgnoreLock));
+        let (content, lock_round, lock_votes) = match lock {
+            Some(lock) => (lock.proposal.clone(), Some(lock.round), lock.votes.clone()),
+            None => (self.feed.clone().unwrap_or_default(), None, Vec::new()),
+        };
+        let mut proposal = Proposal {
+            height: self.height,
+            round: self.round,
+            content,
+            proposer: self.address().clone(),
+            lock_round,
+            lock_votes,
+            signature: Vec::new(),
+        };
+        proposal.signature = self.sign(&proposal_hash(&proposal));
+        self.proposals.insert(self.round, proposal.clone());
+        self.outbox.push_back(FrameRecv::Proposal(proposal));
+        self.prevote();
+    }
+
+    fn prevote(&mut self) {
+        // a locked node prevotes for the lock proposal
+        let lock = self
+            .lock
+            .as_ref()
+            .filter(|_| !self.is_mutated(Mutation::IgnoreLock));
+        let proposal = match (lock, self.proposals.get(&self.round)) {
+            (Some(lock), _) => lock.proposal.clone(),
+            (None, Some(p)) => p.content.clone(),
+            (None, None) => Vec::new(),
+        };
+        self.step = Step::Prevote;
+        self.vote(VoteType::Prevote, proposal.clone());
+        if self.is_mutated(Mutation::DoubleVote) {
+            self.vote(VoteType::Prevote, Vec::new());
+        }
+        if self.is_mutated(Mutation::PrecommitWithoutQuorum) {
+            self.precommit(proposal);
+            return;
+        }
+        self.check_prevote();
+    }
+
+    fn check_prevote(&mut self) {
+        if self.step != Step::Prevote {
+            return;
+        }
+        let votes = self
+            .votes
+            .get(&(self.round, VoteType::Prevote))
+            .cloned()
+            .unwrap_or_default();
+        if let Some(proposal) = self.quorum(&votes) {
+            if proposal.is_empty() {
+                self.lock = None;
+            } else {
+                self.lock = Some(Lock {
+                    round: self.round,
+                    proposal: proposal.clone(),
+                    votes: votes
+                        .into_iter()
+                        .filter(|v| v.proposal == proposal)
+                        .collect(),
+                });
+            }
+            self.precommit(proposal);
+        }
+    }
+
+    fn precommit(&mut self, proposal: Vec<u8>) {
+        self.step = Step::Precommit;
+        self.vote(VoteType::Precommit, proposal);
+        self.check_precommit(self.round);
+    }
+
+    fn check_precommit(&mut self, round: u64) {
+        let votes = self
+            .votes
+            .get(&(round, VoteType::Precommit))
+            .cloned()
+            .unwrap_or_default();
+        let quorum = if self.is_mutated(Mutation::CommitEarly) {
+            self.quorum_by(&votes, |num| num * 3 > self.authority_list.len())
+        } else {
+            self.quorum(&votes)
+        };
+        if let Some(result) = quorum {
+            if result.is_empty() {
+                return;
+            }
+            let mut proof: Vec<Vote> = votes.into_iter().filter(|v| v.proposal == result).collect();
+            if self.is_mutated(Mutation::ShortProof) {
+                proof.truncate(1);
+            }
+            let height = if self.is_mutated(Mutation::CommitNextHeight) {
+                self.height + 1
+            } else {
+                self.height
+            };
+            let commit = Commit {
+                node: 0,
+                height,
+                result,
+                proof,
+            };
+            self.commits.push_back(commit.clone());
+            // polled again in the next height
+            if self.is_mutated(Mutation::RepeatCommit) {
+                self.commits.push_back(commit);
+            } else if self.is_mutated(Mutation::ChangeCommit) {
+                let mut changed = commit;
+                changed.result = changed.result.iter().map(|b| !b).collect();
+                self.commits.push_back(changed);
+            }
+            let height = self.height + 1;
+            self.goto_height(height);
+        }
+    }
+
+    fn quorum(&self, votes: &[Vote]) -> Option<Vec<u8>> {
+        self.quorum_by(votes, |num| self.is_above_threshold(num))
+    }
+
+    fn quorum_by<F: Fn(usize) -> bool>(&self, votes: &[Vote], above: F) -> Option<Vec<u8>> {
+        let mut counts: HashMap<&Vec<u8>, usize> = HashMap::new();
+        for vote in votes.iter() {
+            *counts.entry(&vote.proposal).or_insert(0) += 1;
+        }
+        counts
+            .into_iter()
+            .find(|(_, count)| above(*count))
+            .map(|(p, _)| p.clone())
+    }
+
+    fn vote(&mut self, vote_type: VoteType, proposal: Vec<u8>) {
+        let mut vote = Vote {
+            height: self.height,
+            round: self.round,
+            vote_type: vote_type.clone(),
+            proposal,
+            voter: self.address().clone(),
+            signature: Vec::new(),
+        };
+        vote.signature = self.sign(&vote_hash(&vote));
+        self.votes
+            .entry((self.round, vote_type))
+            .or_default()
+            .push(vote.clone());
+        self.outbox.push_back(FrameRecv::Vote(vote));
+    }
+
+    fn recv(&mut self) -> FrameRecv {
+        loop {
+            if let Some(msg) = self.outbox.pop_front() {
+                return msg;
+            }
+            // the actuator is waiting, so the timeout of the step passes
+            match self.step {
+                Step::Propose => self.prevote(),
+                Step::Prevote => self.precommit(Vec::new()),
+                Step::Precommit => panic!("Reference node has nothing to send!"),
+            }
+        }
+    }
+
+    fn try_get_commit(&mut self) -> Option<Commit> {
+        let commit = self.commits.pop_front();
+        if commit.is_none() && self.step == Step::Precommit {
+            let round = self.round + 1;
+            self.goto_round(round);
+        }
+        commit
+    }
+
+    fn goto_height(&mut self, height: u64) {
+        self.height = height;
+        self.round = 0;
+        self.step = Step::Propose;
+        self.feed = None;
+        self.lock = None;
+        self.proposals.clear();
+        self.votes.clear();
+    }
+
+    fn goto_round(&mut self, round: u64) {
+        self.round = round;
+        self.step = Step::Propose;
+        if self.is_mutated(Mutation::ForgetLock) {
+            self.lock = None;
+        }
+        // handle the messages of the round received before
+        if self.proposals.contains_key(&round) {
+            self.prevote();
+        }
+    }
+
+    fn sign(&self, hash: &[u8]) -> Vec<u8> {
+        match self.crypto.as_ref() {
+            Some((crypto, privkey)) => crypto.sign(privkey, hash),
+            None => Vec::new(),
+        }
+    }
+
+    fn verify(&self, hash: &[u8], signature: &[u8], address: &[u8]) -> bool {
+        match self.crypto.as_ref() {
+            Some((crypto, _)) => crypto.verify(hash, signature, address),
+            None => true,
+        }
+    }
+}
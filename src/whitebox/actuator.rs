@@ -1,11 +1,21 @@
 use crate::whitebox::{
     collection::{storage::*, util::Msg, vote_cache::VoteCache},
-    correctness::test_case::*,
-    error::BftError,
+    correctness::{
+        proposer::{ProposerElection, ProposerReport},
+        random::seed_cases,
+        test_case::*,
+    },
+    crypto::{proposal_hash, vote_hash, Crypto},
+    error::{BftError, ErrorContext, FrameError},
+    invariant::{self, Context, Event, Invariant},
+    observer::{notify, Observer, Step},
+    scheduler::{Delivery, Scheduler},
     *,
 };
 
-use rand::{thread_rng, Rng};
+use rand::{prng::XorShiftRng, thread_rng, Rng, SeedableRng};
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
 use time::Timespec;
 
 /// A whitebox testing actuator.
@@ -16,11 +26,35 @@ pub struct Actuator<T> {
     round: u64,
     lock_round: Option<u64>,
     lock_proposal: Option<Vec<u8>>,
+    lock_votes: Vec<Vote>,
     authority_list: Vec<Address>,
     proposal: Vec<u8>,
     byzantine: Vec<Vec<u8>>,
-    storage: Storage,
+    storage: Box<dyn Storage>,
     vote_cache: VoteCache,
+    round_votes: Vec<Vote>,
+    scheduler: Option<Scheduler>,
+    crypto: Option<Box<dyn Crypto>>,
+    observers: Vec<Box<dyn Observer>>,
+    invariants: Vec<Box<dyn Invariant>>,
+    history: Vec<Event>,
+    // heights of the history to keep, and where each kept height starts in the history
+    history_heights: Option<u64>,
+    height_starts: VecDeque<usize>,
+    // where the test is, for the context of errors and the storage
+    suite: Option<String>,
+    index: usize,
+    unit: Option<BftTestUnit>,
+    step: Option<Step>,
+    seed: Option<u64>,
+    // seed of the random cases of `all_test`, drawn by the first one if not set
+    case_seed: Option<u64>,
+    // random proposals and addresses, seeded by the delivery so a seed reproduces the run
+    rng: XorShiftRng,
+    privkeys: Vec<Vec<u8>>,
+    stale_votes: Vec<Vote>,
+    stale_proposals: Vec<Vec<u8>>,
+    commits: HashMap<u64, Vec<u8>>,
     stime: Timespec,
     htime: Timespec,
 }
@@ -29,80 +63,254 @@ impl<T> Actuator<T>
 where
     T: Support,
 {
-    /// A function to create a new testing acutator.
+    /// A function to create a new testing acutator recording in the SQLite database. The node is
+    /// the first of the authority list, which needs at least four authorities.
     pub fn new(
         function: T,
         height: u64,
         round: u64,
         authority_list: Vec<Address>,
         db_path: &str,
-    ) -> Self {
-        Actuator {
+    ) -> FrameResult<Self> {
+        check_authority_list(&authority_list)?;
+        let storage = SqliteStorage::new(db_path)?;
+        Actuator::with_storage(function, height, round, authority_list, Box::new(storage))
+    }
+
+    /// A function to create a new testing acutator recording in the storage backend. The node is
+    /// the first of the authority list, which needs at least four authorities.
+    pub fn with_storage(
+        function: T,
+        height: u64,
+        round: u64,
+        authority_list: Vec<Address>,
+        storage: Box<dyn Storage>,
+    ) -> FrameResult<Self> {
+        check_authority_list(&authority_list)?;
+        Ok(Actuator {
             function,
             height,
             round,
             lock_round: None,
             lock_proposal: None,
+            lock_votes: Vec::new(),
             authority_list,
             proposal: Vec::new(),
             byzantine: byzantine_proposal(),
-            storage: Storage::new(db_path),
+            storage,
             vote_cache: VoteCache::new(),
+            round_votes: Vec::new(),
+            scheduler: None,
+            crypto: None,
+            observers: Vec::new(),
+            invariants: invariant::builtin(),
+            history: Vec::new(),
+            history_heights: None,
+            height_starts: VecDeque::from(vec![0]),
+            suite: None,
+            index: 0,
+            unit: None,
+            step: None,
+            seed: None,
+            case_seed: None,
+            rng: XorShiftRng::from_rng(thread_rng()).unwrap(),
+            privkeys: Vec::new(),
+            stale_votes: Vec::new(),
+            stale_proposals: Vec::new(),
+            commits: HashMap::new(),
             stime: Timespec::new(0, 0),
             htime: Timespec::new(0, 0),
-        }
+        })
     }
 
-    /// A function to set a new authority list.
-    pub fn set_authority_list(&mut self, authority_list: Vec<Address>) {
+    /// A function to get the ID of the run recorded in the storage by the actuator.
+    pub fn run_id(&self) -> i64 {
+        self.storage.run_id()
+    }
+
+    /// A function to set a new authority list of at least four authorities.
+    pub fn set_authority_list(&mut self, authority_list: Vec<Address>) -> FrameResult<()> {
+        check_authority_list(&authority_list)?;
         self.authority_list = authority_list;
+        Ok(())
     }
 
-    /// A function to do whitebox testing with test cases input.
+    /// A function to set the delivery of messages sent to the node. The messages of a step can
+    /// be duplicated, reordered or delayed to the next step as the delivery, and the random
+    /// proposals are generated by its seed. Return an error if a probability of the delivery is
+    /// out of `[0, 1]`.
+    pub fn set_delivery(&mut self, delivery: Delivery) -> FrameResult<()> {
+        delivery.check()?;
+        self.seed = Some(delivery.seed);
+        self.rng = XorShiftRng::seed_from_u64(delivery.seed);
+        self.scheduler = Some(Scheduler::new(delivery));
+        Ok(())
+    }
+
+    /// A function to set the seed of the random test cases generated by `all_test`. If not set,
+    /// the first `all_test` takes a random one and keeps it. The seed is recorded in the
+    /// storage and reported in the context of errors.
+    pub fn set_case_seed(&mut self, seed: u64) {
+        self.case_seed = Some(seed);
+    }
+
+    /// A function to set the cryptography and the private keys of the authorities except the
+    /// node, in the order of the authority list. After that, the proposals and votes sent to
+    /// the node are signed and the signatures of the node are verified. Return an error if a
+    /// private key is missing, invalid or does not derive the address of its authority.
+    pub fn set_crypto(
+        &mut self,
+        crypto: Box<dyn Crypto>,
+        privkeys: Vec<Vec<u8>>,
+    ) -> FrameResult<()> {
+        for i in 1..self.authority_list.len() {
+            let address = privkeys
+                .get(i - 1)
+                .and_then(|privkey| crypto.pubkey(privkey))
+                .map(|pubkey| crypto.address(&pubkey));
+            if address.as_ref() != Some(&self.authority_list[i]) {
+                return Err(FrameError::InvalidKey(i));
+            }
+        }
+        self.crypto = Some(crypto);
+        self.privkeys = privkeys;
+        Ok(())
+    }
+
+    /// A function to keep the message history of the last heights only, including the current
+    /// one, instead of the whole run. The history is what invariants check and what the
+    /// context of errors is taken from, so a long run can limit its memory with it.
+    pub fn set_history_heights(&mut self, heights: u64) {
+        self.history_heights = Some(heights.max(1));
+    }
+
+    /// A function to add an observer of the messages and the state of the actuator.
+    pub fn add_observer(&mut self, observer: Box<dyn Observer>) {
+        self.observers.push(observer);
+    }
+
+    /// A function to add an invariant checked after every step the node answers.
+    pub fn add_invariant(&mut self, invariant: Box<dyn Invariant>) {
+        self.invariants.push(invariant);
+    }
+
+    /// A function to replace all the invariants, including the built-in ones, for a protocol
+    /// variant with different rules.
+    pub fn set_invariants(&mut self, invariants: Vec<Box<dyn Invariant>>) {
+        self.invariants = invariants;
+    }
+
+    /// A function to do whitebox testing with test cases input. The error is wrapped in
+    /// `BftError::WithContext`, use `root()` to match on it.
     pub fn proc_test(&mut self, cases: BftTest) -> BftResult<()> {
-        self.init();
-        for case in cases.iter() {
-            if case == &SHOULD_COMMIT {
-                if let Some(commit) = self.function.try_get_commit() {
-                    self.storage_msg(Msg::Commit(commit.clone()));
+        self.proc_suite(None, cases)
+    }
+
+    fn proc_suite(&mut self, suite: Option<&str>, cases: BftTest) -> BftResult<()> {
+        if let Some(name) = suite {
+            self.storage.add_suite(name)?;
+        }
+        self.suite = suite.map(|s| s.to_string());
+        let res = self.proc_cases(cases);
+        // flush the storage even if failed, to keep the messages of the failed case
+        let flushed = self.storage.flush().map_err(BftError::from);
+        let res = res
+            .and(flushed)
+            .map_err(|e| self.with_error_context(suite, e));
+        if let Err(e) = res.as_ref() {
+            notify(&mut self.observers, |o| o.on_error(e));
+        }
+        res
+    }
+
+    fn with_error_context(&self, suite: Option<&str>, err: BftError) -> BftError {
+        let actual = self
+            .history
+            .iter()
+            .rev()
+            .find(|e| !matches!(e, Event::Send(_)))
+            .cloned();
+        let ctx = ErrorContext {
+            suite: suite.map(|s| s.to_string()),
+            index: self.index,
+            unit: self.unit,
+            step: self.step,
+            height: self.height,
+            round: self.round,
+            expected: self
+                .lock_proposal
+                .clone()
+                .unwrap_or_else(|| self.proposal.clone()),
+            actual,
+            lock: self.lock_round.zip(self.lock_proposal.clone()),
+            seed: self.seed,
+            case_seed: self.case_seed,
+        };
+        BftError::WithContext(Box::new(err), Box::new(ctx))
+    }
+
+    fn proc_cases(&mut self, cases: BftTest) -> BftResult<()> {
+        self.init()?;
+        for (index, case) in cases.iter().enumerate() {
+            self.index = index;
+            self.unit = Some(*case);
+            self.step = None;
+            if case == &SHOULD_COMMIT || case == &SHOULD_NOT_COMMIT {
+                self.notify_step(Step::Commit);
+                self.deliver(true)?;
+                // an unexpected commit is rejected by the `NoCommit` invariant, unless replaced
+                if let Some(commit) = self.try_get_commit()? {
+                    self.storage_msg(Msg::Commit(commit.clone()), Direction::Recv)?;
                     self.check_commit(commit)?;
-                    let status = self.generate_status();
-                    self.function.send(FrameSend::Status(status));
                     println!(
                         "Height {:?}, use time {:?}",
                         self.height,
                         time::get_time() - self.htime
                     );
                     self.goto_next_height();
+                    self.storage.flush()?;
+                    let status = self.generate_status();
+                    self.send(FrameSend::Status(status))?;
+                } else if case == &SHOULD_NOT_COMMIT {
+                    self.goto_next_round();
                 }
             } else if case == &NULL_ROUND {
                 self.goto_next_round();
-            } else if case == &SHOULD_NOT_COMMIT {
-                if self.function.try_get_commit().is_some() {
-                    // TODO
-                    return Err(BftError::CommitInvalid(self.height));
-                }
-                self.goto_next_round();
+            } else if case == &FORGED_PROPOSAL {
+                self.generate_forged_proposal()?;
+            } else if case == &STALE_PROPOSAL {
+                self.generate_stale_proposal()?;
+            } else if case == &BAD_SIGNED_PROPOSAL {
+                self.generate_bad_signed_proposal()?;
             } else {
                 let prevote = case[0..3].to_vec();
                 let precommit = case[3..6].to_vec();
                 let proposer = self.function.cal_proposer(self.height, self.round);
 
+                self.notify_step(Step::Propose);
                 if proposer == 0 {
                     let feed = self.generate_feed();
                     self.proposal = feed.proposal.clone();
-                    self.function.send(FrameSend::Feed(feed));
+                    self.send(FrameSend::Feed(feed))?;
                     self.check_proposal()?;
                 } else if proposer < self.authority_list.len() {
-                    let proposal = self.generate_proposal(proposer, self.lock_round, Vec::new());
-                    self.storage_msg(Msg::Proposal(proposal.clone()));
-                    self.function.send(FrameSend::Proposal(proposal));
+                    let proposal =
+                        self.generate_proposal(proposer, self.lock_round, self.lock_votes.clone());
+                    self.send(FrameSend::Proposal(proposal))?;
                 } else {
-                    panic!("Proposer index beyond authority list!");
+                    return Err(FrameError::InvalidAuthority(proposer).into());
                 }
-                self.generate_prevote(prevote);
+                let proposal = self
+                    .lock_proposal
+                    .clone()
+                    .unwrap_or_else(|| self.proposal.clone());
+                self.notify_step(Step::Prevote);
+                self.generate_votes(VoteType::Prevote, prevote, proposal)?;
                 self.check_prevote()?;
-                self.generate_precommit(precommit);
+                // precommit for the proposal with +2/3 prevotes in this round or nil
+                let proposal = self.polc_proposal();
+                self.notify_step(Step::Precommit);
+                self.generate_votes(VoteType::Precommit, precommit, proposal)?;
                 self.check_precommit()?;
             }
         }
@@ -110,40 +318,62 @@ where
         Ok(())
     }
 
-    ///
+    /// A function to do whitebox testing with all the test suites, the random cases are
+    /// generated by the case seed. The error is wrapped in `BftError::WithContext`, use
+    /// `root()` to match on it.
     pub fn all_test(&mut self) -> BftResult<()> {
-        let all_test_cases = all_cases();
+        let seed = *self.case_seed.get_or_insert_with(|| thread_rng().gen());
+        println!("Generate test cases by seed {:?}", seed);
+        seed_cases(seed);
+        self.storage.set_case_seed(seed)?;
+        let mut all_test_cases = all_cases();
+        if self.crypto.is_some() {
+            all_test_cases.insert("test signature case".to_string(), signature_cases());
+        }
         for (test_name, test_case) in all_test_cases.into_iter() {
             println!("Do test {:?}", test_name);
-            self.proc_test(test_case)?;
+            self.proc_suite(Some(&test_name), test_case)?;
         }
         println!("All test cases success");
         Ok(())
     }
 
-    fn generate_feed(&self) -> Feed {
-        let mut proposal = vec![0, 0, 0, 0, 0, 0];
-        while self.byzantine.contains(&proposal) {
-            let mut rng = thread_rng();
-            for ii in proposal.iter_mut() {
-                *ii = rng.gen();
+    /// A function to check the proposer calculated by the node against a reference election
+    /// over every height and round in the given ranges. Return the proposer distribution of
+    /// the node if all of them are the same as the reference.
+    pub fn check_proposer<E: ProposerElection>(
+        &self,
+        election: &E,
+        heights: Range<u64>,
+        rounds: Range<u64>,
+    ) -> BftResult<ProposerReport> {
+        let mut report = ProposerReport::new(self.authority_list.len());
+        for height in heights {
+            for round in rounds.clone() {
+                let proposer = self.function.cal_proposer(height, round);
+                if proposer >= self.authority_list.len()
+                    || proposer != election.proposer(height, round)
+                {
+                    return Err(BftError::ProposerDiff(height, round));
+                }
+                report.add(proposer);
             }
         }
-        let res = Feed {
+        Ok(report)
+    }
+
+    fn generate_feed(&mut self) -> Feed {
+        Feed {
             height: self.height,
-            proposal,
-        };
-        self.storage_msg(Msg::Feed(res.clone()));
-        res
+            proposal: self.random_proposal(),
+        }
     }
 
     fn generate_status(&self) -> Status {
-        let res = Status {
+        Status {
             height: self.height,
             authority_list: self.authority_list.clone(),
-        };
-        self.storage_msg(Msg::Status(res.clone()));
-        res
+        }
     }
 
     fn generate_proposal(
@@ -152,117 +382,197 @@ where
         lock_round: Option<u64>,
         lock_votes: Vec<Vote>,
     ) -> Proposal {
-        let mut proposal = vec![0, 0, 0, 0, 0, 0];
-        while self.byzantine.contains(&proposal) {
-            let mut rng = thread_rng();
-            for ii in proposal.iter_mut() {
-                *ii = rng.gen();
-            }
-        }
+        // a locked proposer proposes the lock proposal
+        let proposal = self
+            .lock_proposal
+            .clone()
+            .unwrap_or_else(|| self.random_proposal());
         self.proposal = proposal.clone();
 
-        Proposal {
+        let mut res = Proposal {
             height: self.height,
             round: self.round,
             content: proposal,
             proposer: self.authority_list[auth_index].clone(),
             lock_round,
             lock_votes,
+            signature: Vec::new(),
+        };
+        res.signature = self.sign(self.privkey(auth_index), &proposal_hash(&res));
+        res
+    }
+
+    fn generate_forged_proposal(&mut self) -> FrameResult<()> {
+        // one from an unknown address and one impersonating the node
+        let proposers = vec![
+            self.unknown_signer(),
+            (self.authority_list[0].clone(), self.privkey(1)),
+        ];
+        for (proposer, privkey) in proposers.into_iter() {
+            let mut proposal = Proposal {
+                height: self.height,
+                round: self.round,
+                content: self.random_proposal(),
+                proposer,
+                lock_round: None,
+                lock_votes: Vec::new(),
+                signature: Vec::new(),
+            };
+            proposal.signature = self.sign(privkey, &proposal_hash(&proposal));
+            self.send(FrameSend::Proposal(proposal))?;
         }
+        Ok(())
     }
 
-    fn generate_prevote(&mut self, prevote: Vec<u8>) {
-        let proposal = if self.lock_proposal.is_none() {
-            self.proposal.clone()
-        } else {
-            self.lock_proposal.clone().unwrap()
-        };
+    fn generate_stale_proposal(&mut self) -> FrameResult<()> {
+        for attri in [PAST_HEIGHT, PAST_ROUND, FUTURE_HEIGHT, MAX_HEIGHT].iter() {
+            let (height, round) = self.stale_height_round(*attri);
+            let proposer = self.function.cal_proposer(height, round) % self.authority_list.len();
+            let mut proposal = Proposal {
+                height,
+                round,
+                content: self.random_proposal(),
+                proposer: self.authority_list[proposer].clone(),
+                lock_round: None,
+                lock_votes: Vec::new(),
+                signature: Vec::new(),
+            };
+            proposal.signature = self.sign(self.privkey(proposer), &proposal_hash(&proposal));
+            self.stale_proposals.push(proposal.content.clone());
+            self.send(FrameSend::Proposal(proposal))?;
+        }
+        Ok(())
+    }
 
-        for i in 0..2 {
-            if prevote[i] == NORMAL {
-                let vote = Vote {
-                    height: self.height,
-                    round: self.round,
-                    vote_type: VoteType::Prevote,
-                    proposal: proposal.clone(),
-                    voter: self.authority_list[i + 1].clone(),
-                };
-
-                self.storage_msg(Msg::Vote(vote.clone()));
-                self.function.send(FrameSend::Vote(vote.clone()));
-                self.vote_cache.add(vote);
-            } else if prevote[i] == BYZANTINE {
-                let vote = Vote {
-                    height: self.height,
-                    round: self.round,
-                    vote_type: VoteType::Prevote,
-                    proposal: self.byzantine[i].clone(),
-                    voter: self.authority_list[i + 1].clone(),
-                };
-
-                self.storage_msg(Msg::Vote(vote.clone()));
-                self.function.send(FrameSend::Vote(vote.clone()));
-                self.vote_cache.add(vote);
-            } else if prevote[i] == OFFLINE {
-                return;
-            } else {
-                panic!("Invalid Test Case! {:?}", prevote);
+    fn generate_bad_signed_proposal(&mut self) -> FrameResult<()> {
+        // go to a round proposed by another authority, the node signs its own proposals
+        let mut proposer = self.function.cal_proposer(self.height, self.round);
+        for _ in 1..self.authority_list.len() {
+            if proposer != 0 {
+                break;
             }
+            self.goto_next_round();
+            proposer = self.function.cal_proposer(self.height, self.round);
+        }
+        if proposer == 0 {
+            println!(
+                "Skip bad signed proposals, the node proposes every round of height {:?}",
+                self.height
+            );
+            return Ok(());
+        } else if proposer >= self.authority_list.len() {
+            return Err(FrameError::InvalidAuthority(proposer));
         }
+        // one with a broken signature and one signed by another authority
+        let signers = vec![proposer, proposer % (self.authority_list.len() - 1) + 1];
+        for (i, signer) in signers.into_iter().enumerate() {
+            let mut proposal = Proposal {
+                height: self.height,
+                round: self.round,
+                content: self.random_proposal(),
+                proposer: self.authority_list[proposer].clone(),
+                lock_round: None,
+                lock_votes: Vec::new(),
+                signature: Vec::new(),
+            };
+            proposal.signature = self.sign(self.privkey(signer), &proposal_hash(&proposal));
+            if i == 0 {
+                corrupt_signature(&mut proposal.signature);
+            }
+            self.send(FrameSend::Proposal(proposal))?;
+        }
+        Ok(())
     }
 
-    fn generate_precommit(&mut self, precommit: Vec<u8>) {
-        let proposal = if self.lock_proposal.is_none() {
-            self.proposal.clone()
-        } else {
-            self.lock_proposal.clone().unwrap()
-        };
+    fn generate_votes(
+        &mut self,
+        vote_type: VoteType,
+        attributes: Vec<u8>,
+        proposal: Vec<u8>,
+    ) -> FrameResult<()> {
+        for (i, attri) in attributes.iter().enumerate() {
+            let (voter, proposal, privkey) = match *attri {
+                NORMAL => (
+                    self.authority_list[i + 1].clone(),
+                    proposal.clone(),
+                    self.privkey(i + 1),
+                ),
+                BYZANTINE => (
+                    self.authority_list[i + 1].clone(),
+                    self.byzantine[i].clone(),
+                    self.privkey(i + 1),
+                ),
+                OFFLINE => continue,
+                UNKNOWN => {
+                    let (address, privkey) = self.unknown_signer();
+                    (address, proposal.clone(), privkey)
+                }
+                IMPERSONATOR => (
+                    self.authority_list[0].clone(),
+                    proposal.clone(),
+                    self.privkey(i + 1),
+                ),
+                // repeat the voter of the previous authority
+                DUPLICATE => (
+                    self.authority_list[(i + 2) % 3 + 1].clone(),
+                    proposal.clone(),
+                    self.privkey((i + 2) % 3 + 1),
+                ),
+                PAST_HEIGHT | PAST_ROUND | FUTURE_HEIGHT | MAX_HEIGHT | BAD_SIGNATURE => (
+                    self.authority_list[i + 1].clone(),
+                    proposal.clone(),
+                    self.privkey(i + 1),
+                ),
+                // signed by the next authority
+                WRONG_SIGNER => (
+                    self.authority_list[i + 1].clone(),
+                    proposal.clone(),
+                    self.privkey((i + 1) % 3 + 1),
+                ),
+                _ => return Err(FrameError::InvalidScenario(self.unit.unwrap_or_default())),
+            };
+            let (height, round) = self.stale_height_round(*attri);
+            let mut vote = Vote {
+                height,
+                round,
+                vote_type: vote_type.clone(),
+                proposal,
+                voter,
+                signature: Vec::new(),
+            };
+            vote.signature = self.sign(privkey, &vote_hash(&vote));
+            if *attri == BAD_SIGNATURE {
+                corrupt_signature(&mut vote.signature);
+            }
 
-        for i in 0..2 {
-            if precommit[i] == NORMAL {
-                let vote = Vote {
-                    height: self.height,
-                    round: self.round,
-                    vote_type: VoteType::Precommit,
-                    proposal: proposal.clone(),
-                    voter: self.authority_list[i + 1].clone(),
-                };
-
-                self.storage_msg(Msg::Vote(vote.clone()));
-                self.function.send(FrameSend::Vote(vote.clone()));
-                self.vote_cache.add(vote);
-            } else if precommit[i] == BYZANTINE {
-                let vote = Vote {
-                    height: self.height,
-                    round: self.round,
-                    vote_type: VoteType::Precommit,
-                    proposal: self.byzantine[i].clone(),
-                    voter: self.authority_list[i + 1].clone(),
-                };
-
-                self.storage_msg(Msg::Vote(vote.clone()));
-                self.function.send(FrameSend::Vote(vote.clone()));
-                self.vote_cache.add(vote);
-            } else if precommit[i] == OFFLINE {
-                return;
-            } else {
-                panic!("Invalid Test Case! {:?}", precommit);
+            self.send(FrameSend::Vote(vote.clone()))?;
+            // only the votes of other authorities in this round can be counted by the node
+            if vote.height != self.height || vote.round != self.round {
+                self.stale_votes.push(vote);
+            } else if self.authority_list[1..].contains(&vote.voter)
+                && *attri != BAD_SIGNATURE
+                && *attri != WRONG_SIGNER
+            {
+                // a duplicated voter is counted once
+                if self.vote_cache.add(vote.clone()) {
+                    self.round_votes.push(vote);
+                }
             }
         }
+        Ok(())
     }
 
     fn check_prevote(&mut self) -> BftResult<()> {
-        let vote = self.reveive_vote(VoteType::Prevote)?;
-        let mut clean_flag = true;
+        self.reveive_vote(VoteType::Prevote)?;
+        self.check_invariants(Step::Prevote)?;
 
+        // update the lock by the prevotes of the round
         if let Some(prevote_set) =
             self.vote_cache
-                .get_voteset(self.height, self.height, VoteType::Prevote)
+                .get_voteset(self.height, self.round, VoteType::Prevote)
         {
-            // check prevote condition
             for (p, count) in prevote_set.votes_by_proposal {
                 if self.is_above_threshold(count).is_ok() {
-                    clean_flag = false;
                     if !p.is_empty() {
                         self.set_polc(p);
                     } else {
@@ -270,99 +580,158 @@ where
                     }
                 }
             }
-        } else {
-            return Err(BftError::IllegalVote(vote));
-        }
-        if clean_flag {
-            self.proposal = Vec::new();
         }
         Ok(())
     }
 
     fn check_precommit(&mut self) -> BftResult<()> {
-        let vote = self.reveive_vote(VoteType::Precommit)?;
-        if let Some(prevote_set) =
-            self.vote_cache
-                .get_voteset(self.height, self.height, VoteType::Prevote)
-        {
-            // check precommit condition
-            self.is_above_threshold(prevote_set.count)?;
-            for (p, count) in prevote_set.votes_by_proposal.iter() {
-                if self.is_above_threshold(*count).is_ok() {
-                    if p != &vote.proposal {
-                        return Err(BftError::PrecommitErr(self.height, self.round));
-                    }
-
-                    let polc = prevote_set.extract_polc(
-                        self.height,
-                        self.round,
-                        VoteType::Prevote,
-                        &vote.proposal.clone(),
-                    );
-                    if polc.len() < 3 {
-                        return Err(BftError::PrecommitDiffPoLC(vote));
-                    }
-                }
-            }
-        } else {
-            return Err(BftError::IllegalVote(vote));
-        }
-        Ok(())
+        self.reveive_vote(VoteType::Precommit)?;
+        self.check_invariants(Step::Precommit)
     }
 
     fn check_commit(&mut self, commit: Commit) -> BftResult<()> {
-        // TODO
-        if self.byzantine.contains(&commit.result) || self.proposal != commit.result {
-            return Err(BftError::CommitIncorrect(self.height));
-        }
+        self.check_invariants(Step::Commit)?;
+        self.commits.insert(commit.height, commit.result);
+        Ok(())
+    }
 
-        if let Some(precommit_set) =
-            self.vote_cache
-                .get_voteset(self.height, self.round, VoteType::Precommit)
+    fn check_proposal(&mut self) -> BftResult<()> {
+        self.deliver(false)?;
+        let p = match self.recv()? {
+            FrameRecv::Proposal(p) => p,
+            _ => return Err(BftError::IllegalProposal(self.height, self.round)),
+        };
+        let lock_votes_signed = p
+            .lock_votes
+            .iter()
+            .all(|v| self.verify(&vote_hash(v), &v.signature, &v.voter));
+        if !self.verify(&proposal_hash(&p), &p.signature, &self.authority_list[0])
+            || !lock_votes_signed
         {
-            if precommit_set
-                .extract_polc(self.height, self.round, VoteType::Precommit, &commit.result)
-                .len()
-                < 3
-            {
-                return Err(BftError::CommitIncorrect(self.height));
-            }
+            return Err(BftError::IllegalSignature(self.height, self.round));
         }
-        Ok(())
+        self.check_invariants(Step::Propose)
     }
 
-    fn check_proposal(&self) -> BftResult<()> {
-        match self.function.recv() {
-            FrameRecv::Proposal(p) => Ok(p),
-            _ => Err(BftError::IllegalProposal(self.height, self.round)),
-        }
-        .and_then(|p| {
-            if self.lock_round.is_some() {
-                if p.lock_round.is_none() || Some(p.content) != self.lock_proposal {
-                    return Err(BftError::IllegalProposal(self.height, self.round));
-                }
-            } else if p.lock_round.is_some() {
-                return Err(BftError::IllegalProposal(self.height, self.round));
+    fn check_invariants(&mut self, step: Step) -> BftResult<()> {
+        self.with_context(step, |ctx, invariants| {
+            for invariant in invariants.iter_mut() {
+                invariant.check(ctx)?;
             }
             Ok(())
         })
     }
 
+    fn with_context<F, R>(&mut self, step: Step, f: F) -> R
+    where
+        F: FnOnce(&Context, &mut [Box<dyn Invariant>]) -> R,
+    {
+        let votes = self.vote_cache.votes_of_height(self.height);
+        let ctx = Context {
+            height: self.height,
+            round: self.round,
+            step,
+            authority_list: &self.authority_list,
+            lock_round: self.lock_round,
+            lock_proposal: self.lock_proposal.as_deref(),
+            proposal: &self.proposal,
+            votes: &votes,
+            stale_votes: &self.stale_votes,
+            stale_proposals: &self.stale_proposals,
+            byzantine: &self.byzantine,
+            commits: &self.commits,
+            expect_commit: self.unit != Some(SHOULD_NOT_COMMIT),
+            history: &self.history,
+            crypto: self.crypto.as_deref(),
+        };
+        f(&ctx, &mut self.invariants)
+    }
+
     fn reveive_vote(&mut self, vote_type: VoteType) -> BftResult<Vote> {
-        let mut vote;
-        match self.function.recv() {
-            FrameRecv::Proposal(p) => return Err(BftError::AbnormalProposal(p)),
-            FrameRecv::Vote(v) => vote = v,
+        self.deliver(false)?;
+        let vote = match self.recv()? {
+            FrameRecv::Proposal(p) => return Err(BftError::AbnormalProposal(Box::new(p))),
+            FrameRecv::Vote(v) => v,
+        };
+        if !self.verify(&vote_hash(&vote), &vote.signature, &self.authority_list[0]) {
+            return Err(BftError::IllegalSignature(self.height, self.round));
         }
-        if vote.vote_type == vote_type || self.byzantine.contains(&vote.proposal) {
+        if vote.height != self.height || vote.round != self.round {
+            return Err(BftError::WrongHeightRound(vote));
+        }
+        if vote.vote_type != vote_type || self.byzantine.contains(&vote.proposal) {
             // check vote type and vote proposal
             return Err(BftError::IllegalVote(vote));
         }
-        self.vote_cache.add(vote.clone());
-        self.storage_msg(Msg::Vote(vote.clone()));
+        if self.vote_cache.add(vote.clone()) {
+            self.round_votes.push(vote.clone());
+        }
         Ok(vote)
     }
 
+    fn stale_height_round(&self, attri: u8) -> (u64, u64) {
+        match attri {
+            PAST_HEIGHT => (self.height.wrapping_sub(1), self.round),
+            // there is no past round in round 0, use the past height instead
+            PAST_ROUND if self.round == 0 => (self.height.wrapping_sub(1), self.round),
+            PAST_ROUND => (self.height, self.round - 1),
+            FUTURE_HEIGHT => (self.height.saturating_add(FUTURE_HEIGHT_GAP), self.round),
+            MAX_HEIGHT => (u64::MAX, self.round),
+            _ => (self.height, self.round),
+        }
+    }
+
+    fn random_proposal(&mut self) -> Vec<u8> {
+        let mut proposal = vec![0, 0, 0, 0, 0, 0];
+        while self.byzantine.contains(&proposal) {
+            for ii in proposal.iter_mut() {
+                *ii = self.rng.gen();
+            }
+        }
+        proposal
+    }
+
+    fn privkey(&self, index: usize) -> Option<Vec<u8>> {
+        // private keys are of the authorities except the node
+        index
+            .checked_sub(1)
+            .and_then(|i| self.privkeys.get(i))
+            .cloned()
+    }
+
+    fn sign(&self, privkey: Option<Vec<u8>>, hash: &[u8]) -> Vec<u8> {
+        match (self.crypto.as_ref(), privkey) {
+            (Some(crypto), Some(privkey)) => crypto.sign(&privkey, hash),
+            _ => Vec::new(),
+        }
+    }
+
+    fn verify(&self, hash: &[u8], signature: &[u8], address: &[u8]) -> bool {
+        match self.crypto.as_ref() {
+            Some(crypto) => crypto.verify(hash, signature, address),
+            None => true,
+        }
+    }
+
+    fn unknown_signer(&mut self) -> (Address, Option<Vec<u8>>) {
+        if let Some(crypto) = self.crypto.as_ref() {
+            let (privkey, pubkey) = crypto.gen_keypair();
+            (crypto.address(&pubkey), Some(privkey))
+        } else {
+            (self.unknown_address(), None)
+        }
+    }
+
+    fn unknown_address(&mut self) -> Address {
+        let len = self.authority_list[0].len();
+        let mut address = self.authority_list[0].clone();
+        while self.authority_list.contains(&address) {
+            let rng = &mut self.rng;
+            address = (0..len).map(|_| rng.gen()).collect();
+        }
+        address
+    }
+
     fn is_above_threshold(&self, num: usize) -> BftResult<()> {
         if num * 3 <= self.authority_list.len() * 2 {
             return Err(BftError::ShouldNotPrecommit(self.height, self.round));
@@ -370,31 +739,160 @@ where
         Ok(())
     }
 
+    fn polc_proposal(&mut self) -> Vec<u8> {
+        self.vote_cache
+            .get_voteset(self.height, self.round, VoteType::Prevote)
+            .and_then(|vs| {
+                vs.votes_by_proposal
+                    .into_iter()
+                    .find(|(_, count)| self.is_above_threshold(*count).is_ok())
+            })
+            .map(|(p, _)| p)
+            .unwrap_or_default()
+    }
+
     fn set_polc(&mut self, proposal: Vec<u8>) {
         self.proposal = proposal.clone();
         self.lock_round = Some(self.round);
+        self.lock_votes = self
+            .round_votes
+            .iter()
+            .filter(|v| v.vote_type == VoteType::Prevote && v.proposal == proposal)
+            .cloned()
+            .collect();
+        let lock_round = self.round;
+        notify(&mut self.observers, |o| {
+            o.on_lock(Some((lock_round, &proposal)))
+        });
         self.lock_proposal = Some(proposal);
     }
 
     fn clean_polc(&mut self) {
+        if self.lock_round.is_some() {
+            notify(&mut self.observers, |o| o.on_lock(None));
+        }
         self.proposal = Vec::new();
         self.lock_round = None;
         self.lock_proposal = None;
+        self.lock_votes = Vec::new();
+    }
+
+    fn send(&mut self, msg: FrameSend) -> FrameResult<()> {
+        let status = matches!(msg, FrameSend::Status(_));
+        if let Some(scheduler) = self.scheduler.as_mut() {
+            scheduler.push(msg);
+            // status ends a height, so send all the messages with it
+            if status {
+                self.deliver(true)?;
+            }
+            Ok(())
+        } else {
+            self.send_to_node(msg)
+        }
+    }
+
+    fn send_to_node(&mut self, msg: FrameSend) -> FrameResult<()> {
+        notify(&mut self.observers, |o| o.on_send(&msg));
+        self.history.push(Event::Send(msg.clone()));
+        // record what the node gets, after the delivery duplicates, delays or reorders it
+        let record = match &msg {
+            FrameSend::Proposal(p) => Msg::Proposal(p.clone()),
+            FrameSend::Vote(v) => Msg::Vote(v.clone()),
+            FrameSend::Feed(f) => Msg::Feed(f.clone()),
+            FrameSend::Status(s) => Msg::Status(s.clone()),
+        };
+        self.storage_msg(record, Direction::Send)?;
+        self.function.send(msg);
+        Ok(())
+    }
+
+    fn recv(&mut self) -> FrameResult<FrameRecv> {
+        let msg = self.function.recv();
+        notify(&mut self.observers, |o| o.on_recv(&msg));
+        self.history.push(Event::Recv(msg.clone()));
+        // record whatever the node answers before it is checked
+        let record = match &msg {
+            FrameRecv::Proposal(p) => Msg::Proposal(p.clone()),
+            FrameRecv::Vote(v) => Msg::Vote(v.clone()),
+        };
+        self.storage_msg(record, Direction::Recv)?;
+        Ok(msg)
     }
 
-    fn storage_msg(&self, msg: Msg) {
-        let res = self.storage.insert(msg.clone());
-        if res.is_err() {
-            panic!("SQLite Error {:?} when insert {:?}", res, msg);
+    fn try_get_commit(&mut self) -> FrameResult<Option<Commit>> {
+        let commit = self.function.try_get_commit();
+        if let Some(commit) = commit.as_ref() {
+            notify(&mut self.observers, |o| o.on_commit(commit));
+            self.history.push(Event::Commit(commit.clone()));
+        } else {
+            // record the poll to replay the trace as it is
+            let meta = self.msg_meta(Direction::Recv);
+            self.storage.insert_poll(self.height, self.round, &meta)?;
+        }
+        Ok(commit)
+    }
+
+    fn notify_step(&mut self, step: Step) {
+        self.step = Some(step);
+        let (height, round) = (self.height, self.round);
+        notify(&mut self.observers, |o| o.on_step(height, round, step));
+    }
+
+    fn notify_height_round(&mut self) {
+        let (height, round) = (self.height, self.round);
+        notify(&mut self.observers, |o| o.on_height_round(height, round));
+    }
+
+    fn deliver(&mut self, all: bool) -> FrameResult<()> {
+        if let Some(scheduler) = self.scheduler.as_mut() {
+            let msgs = if all {
+                scheduler.flush()
+            } else {
+                scheduler.step()
+            };
+            for msg in msgs.into_iter() {
+                self.send_to_node(msg)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn storage_msg(&self, msg: Msg, direction: Direction) -> FrameResult<()> {
+        self.storage.insert(msg, &self.msg_meta(direction))?;
+        Ok(())
+    }
+
+    fn msg_meta(&self, direction: Direction) -> MsgMeta {
+        MsgMeta {
+            timestamp: time::get_time(),
+            direction,
+            step: self.step,
+            suite: self.suite.clone(),
         }
     }
 
     fn goto_next_height(&mut self) {
+        self.height_starts.push_back(self.history.len());
+        if let Some(heights) = self.history_heights {
+            let pruned = self.height_starts.len().saturating_sub(heights as usize);
+            if pruned > 0 {
+                let start = self.height_starts[pruned];
+                self.history.drain(..start);
+                self.height_starts.drain(..pruned);
+                for s in self.height_starts.iter_mut() {
+                    *s -= start;
+                }
+            }
+        }
         self.vote_cache.clear_prevote_count();
         self.clean_polc();
+        self.round_votes.clear();
+        self.stale_votes.clear();
+        self.stale_proposals.clear();
         self.round = 0;
         self.height += 1;
         self.htime = time::get_time();
+        self.notify_height_round();
     }
 
     fn goto_next_round(&mut self) {
@@ -403,13 +901,237 @@ where
         } else {
             self.proposal = self.lock_proposal.clone().unwrap();
         }
+        self.round_votes.clear();
+        self.stale_votes.clear();
+        self.stale_proposals.clear();
         self.round += 1;
+        self.notify_height_round();
     }
 
-    fn init(&mut self) {
+    fn init(&mut self) -> FrameResult<()> {
+        if let Some(seed) = self.seed {
+            self.storage.set_seed(seed)?;
+        }
         let init = self.generate_status();
-        self.storage_msg(Msg::Status(init.clone()));
-        self.function.send(FrameSend::Status(init));
+        self.send(FrameSend::Status(init))?;
+        self.stime = time::get_time();
         self.htime = time::get_time();
+        self.notify_height_round();
+        Ok(())
+    }
+}
+
+fn corrupt_signature(signature: &mut Vec<u8>) {
+    match signature.last_mut() {
+        Some(byte) => *byte ^= 1,
+        None => signature.push(1),
+    }
+}
+
+// the node and the three authorities the actuator simulates
+fn check_authority_list(authority_list: &[Address]) -> FrameResult<()> {
+    if authority_list.len() < 4 {
+        return Err(FrameError::TooFewAuthorities(authority_list.len()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::whitebox::{collection::backend::MemoryStorage, crypto::Ed25519};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    // what the node votes for
+    #[derive(Clone, Copy)]
+    enum Answer {
+        Proposal,
+        Nil,
+        Other,
+    }
+
+    // a node which is never the proposer, and answers the scripted votes for the last proposal
+    // sent to it
+    #[derive(Clone, Default)]
+    struct ScriptedNode {
+        proposal: Rc<RefCell<Option<Proposal>>>,
+        answers: Rc<RefCell<VecDeque<(VoteType, Answer)>>>,
+        votes: Rc<RefCell<Vec<Vote>>>,
+    }
+
+    impl Support for ScriptedNode {
+        fn send(&self, msg: FrameSend) {
+            match msg {
+                FrameSend::Proposal(p) => *self.proposal.borrow_mut() = Some(p),
+                FrameSend::Vote(v) => self.votes.borrow_mut().push(v),
+                _ => (),
+            }
+        }
+
+        fn recv(&self) -> FrameRecv {
+            let (vote_type, answer) = self.answers.borrow_mut().pop_front().unwrap();
+            let p = self.proposal.borrow().clone().unwrap();
+            let proposal = match answer {
+                Answer::Proposal => p.content,
+                Answer::Nil => Vec::new(),
+                Answer::Other => vec![9; 6],
+            };
+            FrameRecv::Vote(Vote {
+                height: p.height,
+                round: p.round,
+                vote_type,
+                proposal,
+                voter: vec![0],
+                signature: Vec::new(),
+            })
+        }
+
+        fn try_get_commit(&self) -> Option<Commit> {
+            None
+        }
+
+        fn stop(&self) {}
+
+        fn cal_proposer(&self, _height: u64, _round: u64) -> usize {
+            1
+        }
+    }
+
+    fn run(case: BftTestUnit, answers: Vec<(VoteType, Answer)>) -> (BftResult<()>, ScriptedNode) {
+        let node = ScriptedNode::default();
+        node.answers.borrow_mut().extend(answers);
+        let authority_list = (0..4).map(|i| vec![i]).collect();
+        let mut actuator = Actuator::new(node.clone(), 0, 0, authority_list, ":memory:").unwrap();
+        (actuator.proc_test(vec![case]), node)
+    }
+
+    #[test]
+    fn test_offline_authority() {
+        // an offline authority does not silence the ones after it
+        let answers = vec![
+            (VoteType::Prevote, Answer::Proposal),
+            (VoteType::Precommit, Answer::Proposal),
+        ];
+        let (res, node) = run([0, 1, 1, 0, 1, 1], answers);
+        res.unwrap();
+        let voters: Vec<Vec<u8>> = node
+            .votes
+            .borrow()
+            .iter()
+            .map(|v| v.voter.clone())
+            .collect();
+        assert_eq!(voters, vec![vec![2], vec![3], vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn test_precommit_quorum() {
+        // 4 prevotes, but only 2 of them for the proposal
+        let precommit = |answer| {
+            let answers = vec![
+                (VoteType::Prevote, Answer::Proposal),
+                (VoteType::Precommit, answer),
+            ];
+            run([1, 2, 2, 1, 1, 1], answers).0
+        };
+        assert!(precommit(Answer::Proposal).is_err());
+        assert!(precommit(Answer::Nil).is_ok());
+    }
+
+    #[test]
+    fn test_prevote_proposal() {
+        let (res, _) = run([1, 1, 1, 1, 1, 1], vec![(VoteType::Prevote, Answer::Other)]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_duplicate_lock_votes() {
+        let node = ScriptedNode::default();
+        node.answers.borrow_mut().extend(vec![
+            (VoteType::Prevote, Answer::Proposal),
+            (VoteType::Precommit, Answer::Proposal),
+        ]);
+        let authority_list = (0..4).map(|i| vec![i]).collect();
+        let mut actuator = Actuator::new(node, 0, 0, authority_list, ":memory:").unwrap();
+        // the third authority repeats the prevote of the second one
+        actuator
+            .proc_test(vec![[1, 1, DUPLICATE, 1, 1, 1]])
+            .unwrap();
+        let mut voters: Vec<Vec<u8>> = actuator
+            .lock_votes
+            .iter()
+            .map(|v| v.voter.clone())
+            .collect();
+        voters.sort();
+        assert_eq!(voters, vec![vec![0], vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn test_record_delivery() {
+        let node = ScriptedNode::default();
+        node.answers.borrow_mut().extend(vec![
+            (VoteType::Prevote, Answer::Proposal),
+            (VoteType::Precommit, Answer::Proposal),
+        ]);
+        let storage = MemoryStorage::new();
+        let authority_list = (0..4).map(|i| vec![i]).collect();
+        let mut actuator = Actuator::with_storage(
+            node.clone(),
+            0,
+            0,
+            authority_list,
+            Box::new(storage.clone()),
+        )
+        .unwrap();
+        actuator
+            .set_delivery(Delivery {
+                seed: 3,
+                duplicate: 1.0,
+                delay: 0.0,
+                reorder: true,
+            })
+            .unwrap();
+        actuator.proc_test(vec![[1; 6]]).unwrap();
+        // every vote is recorded as many times as the node gets it
+        let sent: Vec<Vote> = storage
+            .records()
+            .into_iter()
+            .filter_map(|r| match (r.direction, r.msg) {
+                (Direction::Send, Msg::Vote(v)) => Some(v),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sent.len(), 12);
+        assert_eq!(sent, *node.votes.borrow());
+    }
+
+    #[test]
+    fn test_set_crypto() {
+        let crypto = Ed25519;
+        let (privkeys, authority_list): (Vec<_>, Vec<_>) = (0..4)
+            .map(|_| {
+                let (privkey, pubkey) = crypto.gen_keypair();
+                (privkey, crypto.address(&pubkey))
+            })
+            .unzip();
+        let node = ScriptedNode::default();
+        let mut actuator = Actuator::new(node, 0, 0, authority_list, ":memory:").unwrap();
+        let set_crypto =
+            |actuator: &mut Actuator<ScriptedNode>, privkeys: Vec<Vec<u8>>| match actuator
+                .set_crypto(Box::new(crypto), privkeys)
+            {
+                Ok(()) => None,
+                Err(FrameError::InvalidKey(i)) => Some(i),
+                Err(e) => panic!("Unexpected error {:?}", e),
+            };
+        // a missing key, an invalid key and a key of another authority
+        assert_eq!(set_crypto(&mut actuator, privkeys[1..3].to_vec()), Some(3));
+        let mut invalid = privkeys[1..].to_vec();
+        invalid[0] = vec![1, 2, 3];
+        assert_eq!(set_crypto(&mut actuator, invalid), Some(1));
+        assert_eq!(set_crypto(&mut actuator, privkeys[..3].to_vec()), Some(1));
+        assert!(actuator.crypto.is_none());
+        assert_eq!(set_crypto(&mut actuator, privkeys[1..].to_vec()), None);
     }
 }
@@ -0,0 +1,332 @@
+use crate::whitebox::{
+    collection::{
+        query::{Filter, TraceReader},
+        storage::Direction,
+        util::Msg,
+        vote_cache::VoteCache,
+    },
+    error::BftError,
+    *,
+};
+
+use std::collections::HashMap;
+
+/// A safety property of Tendermint checked over a recorded run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Property {
+    /// No two commits of different results at a height.
+    ConflictingCommit,
+    /// No precommit for a proposal without +2/3 prevotes for it before in the round.
+    PrecommitWithoutQuorum,
+    /// No prevote for another proposal than the lock one, unless +2/3 prevotes for something
+    /// else are seen in a round after the lock round.
+    PrevoteAgainstLock,
+    /// No two votes of a voter of different proposals in a round and vote type.
+    DoubleVote,
+}
+
+/// A violation of a safety property in a recorded run.
+#[derive(Debug)]
+pub struct Violation {
+    /// Sequence number of the violating message.
+    pub seq: u64,
+    /// The violated property.
+    pub property: Property,
+    /// The error the actuator would report for the message.
+    pub err: BftError,
+}
+
+/// A checker of the safety properties over the runs recorded in the storage, without a node.
+/// Any trace in the same schema can be checked, such as logs of production nodes.
+pub struct SafetyChecker {
+    authority_list: Vec<Address>,
+    voters: Option<Vec<Address>>,
+}
+
+impl SafetyChecker {
+    /// A function to create a checker of the votes of every authority.
+    pub fn new(authority_list: Vec<Vec<u8>>) -> Self {
+        SafetyChecker {
+            authority_list,
+            voters: None,
+        }
+    }
+
+    /// A function to create a checker of the votes of the given voters only, such as the node
+    /// under test of a run where the other authorities are scripted by the test cases.
+    pub fn with_voters(authority_list: Vec<Vec<u8>>, voters: Vec<Vec<u8>>) -> Self {
+        SafetyChecker {
+            authority_list,
+            voters: Some(voters),
+        }
+    }
+
+    /// A function to check the run, return every violation in the recorded order. Only the
+    /// votes received from the node are audited. The votes sent to it, including the ones
+    /// the actuator sends in the name of an audited voter, are counted for the quorums but
+    /// never taken as votes of their voter. Votes of non-authorities are ignored.
+    pub fn check(&self, reader: &TraceReader, run_id: i64) -> FrameResult<Vec<Violation>> {
+        // every vote seen by the node, and the votes of the node
+        let mut cache = VoteCache::new();
+        let mut audited = VoteCache::new();
+        let mut commits: HashMap<u64, Vec<u8>> = HashMap::new();
+        // voter -> height, round and proposal of the last precommit for a proposal
+        let mut locks: HashMap<Address, (u64, u64, Hash)> = HashMap::new();
+        let mut res = Vec::new();
+
+        for record in reader.load(run_id, &Filter::default())?.into_iter() {
+            let seq = record.seq;
+            let vote = match record.msg {
+                Msg::Vote(v) => v,
+                Msg::Commit(c) => {
+                    let result = commits.entry(c.height).or_insert_with(|| c.result.clone());
+                    if *result != c.result {
+                        res.push(Violation {
+                            seq,
+                            property: Property::ConflictingCommit,
+                            err: BftError::CommitDiff(c.height),
+                        });
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            if !self.authority_list.contains(&vote.voter) {
+                continue;
+            }
+            if record.direction == Direction::Recv && self.is_audited(&vote.voter) {
+                if let Some(property) = self.check_vote(&mut cache, &mut audited, &locks, &vote) {
+                    let err = match property {
+                        Property::PrecommitWithoutQuorum => {
+                            BftError::ShouldNotPrecommit(vote.height, vote.round)
+                        }
+                        _ => BftError::IllegalVote(vote.clone()),
+                    };
+                    res.push(Violation { seq, property, err });
+                }
+                if vote.vote_type == VoteType::Precommit && !vote.proposal.is_empty() {
+                    locks.insert(
+                        vote.voter.clone(),
+                        (vote.height, vote.round, vote.proposal.clone()),
+                    );
+                }
+                audited.add(vote.clone());
+            }
+            cache.add(vote);
+        }
+        Ok(res)
+    }
+
+    fn is_audited(&self, voter: &[u8]) -> bool {
+        match self.voters.as_ref() {
+            Some(voters) => voters.iter().any(|v| v.as_slice() == voter),
+            None => true,
+        }
+    }
+
+    fn is_above_threshold(&self, num: usize) -> bool {
+        num * 3 > self.authority_list.len() * 2
+    }
+
+    fn check_vote(
+        &self,
+        cache: &mut VoteCache,
+        audited: &mut VoteCache,
+        locks: &HashMap<Address, (u64, u64, Hash)>,
+        vote: &Vote,
+    ) -> Option<Property> {
+        let voteset = audited.get_voteset(vote.height, vote.round, vote.vote_type.clone());
+        if voteset
+            .and_then(|vs| vs.votes_by_sender.get(&vote.voter).cloned())
+            .is_some_and(|p| p != vote.proposal)
+        {
+            return Some(Property::DoubleVote);
+        }
+        if vote.proposal.is_empty() {
+            return None;
+        }
+
+        match vote.vote_type {
+            VoteType::Precommit => {
+                let count = cache
+                    .get_voteset(vote.height, vote.round, VoteType::Prevote)
+                    .and_then(|vs| vs.votes_by_proposal.get(&vote.proposal).cloned())
+                    .unwrap_or(0);
+                if !self.is_above_threshold(count) {
+                    return Some(Property::PrecommitWithoutQuorum);
+                }
+            }
+            VoteType::Prevote => {
+                let (lock_round, lock_proposal) = match locks.get(&vote.voter) {
+                    Some((h, r, p)) if *h == vote.height && *r < vote.round => (*r, p),
+                    _ => return None,
+                };
+                if vote.proposal == *lock_proposal {
+                    return None;
+                }
+                // unlocked by a PoLC of something else after the lock round
+                let unlocked = (lock_round + 1..vote.round).any(|round| {
+                    cache
+                        .get_voteset(vote.height, round, VoteType::Prevote)
+                        .is_some_and(|vs| {
+                            vs.votes_by_proposal
+                                .iter()
+                                .any(|(p, n)| p != lock_proposal && self.is_above_threshold(*n))
+                        })
+                });
+                if !unlocked {
+                    return Some(Property::PrevoteAgainstLock);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testkit::{Mutation, ReferenceNode};
+    use crate::whitebox::{
+        actuator::Actuator,
+        collection::storage::{remove_db, Direction, MsgMeta, SqliteStorage, Storage},
+        correctness::test_case::{SHOULD_COMMIT, SHOULD_NOT_COMMIT},
+    };
+    use rand::random;
+
+    // record a round without a prevote quorum and a committed one run by the reference node,
+    // and check the votes of the node
+    fn audit(mutation: Option<Mutation>) -> Vec<Violation> {
+        let path = std::env::temp_dir().join(format!("bft_audit_{}.db", random::<u64>()));
+        let path = path.to_str().unwrap();
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let node = match mutation {
+            Some(m) => ReferenceNode::with_mutation(authority_list.clone(), m),
+            None => ReferenceNode::new(authority_list.clone()),
+        };
+        let mut actuator = Actuator::new(node, 0, 0, authority_list.clone(), path).unwrap();
+        let cases = vec![
+            [1, 2, 0, 1, 2, 0],
+            SHOULD_NOT_COMMIT,
+            [1, 1, 1, 1, 1, 1],
+            SHOULD_COMMIT,
+        ];
+        let _ = actuator.proc_test(cases);
+        let run_id = actuator.run_id();
+
+        let reader = TraceReader::open(path).unwrap();
+        let voters = vec![authority_list[0].clone()];
+        let res = SafetyChecker::with_voters(authority_list, voters)
+            .check(&reader, run_id)
+            .unwrap();
+        remove_db(path);
+        res
+    }
+
+    #[test]
+    fn test_safety_checker() {
+        assert!(audit(None).is_empty());
+        let cases = vec![
+            (Mutation::DoubleVote, Property::DoubleVote),
+            (
+                Mutation::PrecommitWithoutQuorum,
+                Property::PrecommitWithoutQuorum,
+            ),
+        ];
+        for (mutation, property) in cases.into_iter() {
+            let violations = audit(Some(mutation));
+            assert!(
+                violations.iter().any(|v| v.property == property),
+                "{:?} is not caught: {:?}",
+                mutation,
+                violations
+            );
+        }
+    }
+
+    fn vote(round: u64, vote_type: VoteType, proposal: &[u8], voter: u8) -> Msg {
+        Msg::Vote(Vote {
+            height: 1,
+            round,
+            vote_type,
+            proposal: proposal.to_vec(),
+            voter: vec![voter],
+            signature: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_prevote_against_lock() {
+        let path = std::env::temp_dir().join(format!("bft_audit_{}.db", random::<u64>()));
+        let path = path.to_str().unwrap();
+        let storage = SqliteStorage::new(path).unwrap();
+        let meta = MsgMeta {
+            timestamp: time::get_time(),
+            direction: Direction::Recv,
+            step: None,
+            suite: None,
+        };
+        // every authority locks on [1] in round 0, then prevotes [2] in round 1, and all but
+        // the first one prevote nil in round 2 before it prevotes [2] again in round 3
+        let mut msgs = Vec::new();
+        for i in 0..4 {
+            msgs.push(vote(0, VoteType::Prevote, &[1], i));
+        }
+        for i in 0..4 {
+            msgs.push(vote(0, VoteType::Precommit, &[1], i));
+        }
+        msgs.push(vote(1, VoteType::Prevote, &[2], 0));
+        for i in 1..4 {
+            msgs.push(vote(2, VoteType::Prevote, &[], i));
+        }
+        msgs.push(vote(3, VoteType::Prevote, &[2], 0));
+        for msg in msgs.into_iter() {
+            storage.insert(msg, &meta).unwrap();
+        }
+        storage.flush().unwrap();
+
+        let reader = TraceReader::open(path).unwrap();
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let violations = SafetyChecker::new(authority_list)
+            .check(&reader, storage.run_id())
+            .unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].seq, 8);
+        assert_eq!(violations[0].property, Property::PrevoteAgainstLock);
+        remove_db(path);
+    }
+
+    #[test]
+    fn test_sent_votes() {
+        let path = std::env::temp_dir().join(format!("bft_audit_{}.db", random::<u64>()));
+        let path = path.to_str().unwrap();
+        let storage = SqliteStorage::new(path).unwrap();
+        let meta = |direction| MsgMeta {
+            timestamp: time::get_time(),
+            direction,
+            step: None,
+            suite: None,
+        };
+        // the actuator impersonates the node with a prevote and a precommit of another
+        // proposal, then the node prevotes [1] in round 0 and round 1
+        let msgs = vec![
+            (vote(0, VoteType::Prevote, &[9], 0), Direction::Send),
+            (vote(0, VoteType::Precommit, &[9], 0), Direction::Send),
+            (vote(0, VoteType::Prevote, &[1], 0), Direction::Recv),
+            (vote(1, VoteType::Prevote, &[1], 0), Direction::Recv),
+        ];
+        for (msg, direction) in msgs.into_iter() {
+            storage.insert(msg, &meta(direction)).unwrap();
+        }
+        storage.flush().unwrap();
+
+        let reader = TraceReader::open(path).unwrap();
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let voters = vec![authority_list[0].clone()];
+        let violations = SafetyChecker::with_voters(authority_list, voters)
+            .check(&reader, storage.run_id())
+            .unwrap();
+        assert!(violations.is_empty(), "{:?}", violations);
+        remove_db(path);
+    }
+}
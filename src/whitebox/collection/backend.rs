@@ -0,0 +1,295 @@
+use crate::whitebox::{
+    collection::{
+        export::{RunHeader, TraceEvent},
+        query::{Poll, Record},
+        storage::{MsgMeta, Storage},
+        util::Msg,
+    },
+    FrameResult,
+};
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::rc::Rc;
+
+#[derive(Debug, Default)]
+struct MemoryTrace {
+    seed: Option<u64>,
+    case_seed: Option<u64>,
+    suites: Vec<String>,
+    records: Vec<Record>,
+    polls: Vec<Poll>,
+    seq: u64,
+}
+
+/// An in-memory storage. It is cheap to clone and the clones share the trace, so the run can
+/// be read after the storage is given to an actuator. The run ID is always 0.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage(Rc<RefCell<MemoryTrace>>);
+
+impl MemoryStorage {
+    /// A function to create an empty storage.
+    pub fn new() -> Self {
+        MemoryStorage::default()
+    }
+
+    /// A function to get the seed of the message delivery if set.
+    pub fn seed(&self) -> Option<u64> {
+        self.0.borrow().seed
+    }
+
+    /// A function to get the seed of the random test cases if generated.
+    pub fn case_seed(&self) -> Option<u64> {
+        self.0.borrow().case_seed
+    }
+
+    /// A function to get the names of the suites run.
+    pub fn suites(&self) -> Vec<String> {
+        self.0.borrow().suites.clone()
+    }
+
+    /// A function to get the recorded messages in the recorded order.
+    pub fn records(&self) -> Vec<Record> {
+        self.0.borrow().records.clone()
+    }
+
+    /// A function to get the commit polls which get nothing from the node.
+    pub fn polls(&self) -> Vec<Poll> {
+        self.0.borrow().polls.clone()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn run_id(&self) -> i64 {
+        0
+    }
+
+    fn set_seed(&self, seed: u64) -> FrameResult<()> {
+        self.0.borrow_mut().seed = Some(seed);
+        Ok(())
+    }
+
+    fn set_case_seed(&self, seed: u64) -> FrameResult<()> {
+        self.0.borrow_mut().case_seed = Some(seed);
+        Ok(())
+    }
+
+    fn add_suite(&self, suite: &str) -> FrameResult<()> {
+        self.0.borrow_mut().suites.push(suite.to_string());
+        Ok(())
+    }
+
+    fn insert(&self, msg: Msg, meta: &MsgMeta) -> FrameResult<()> {
+        let mut trace = self.0.borrow_mut();
+        let record = Record {
+            seq: trace.seq,
+            timestamp: meta.timestamp,
+            direction: meta.direction,
+            step: meta.step,
+            suite: meta.suite.clone(),
+            msg,
+        };
+        trace.records.push(record);
+        trace.seq += 1;
+        Ok(())
+    }
+
+    fn insert_poll(&self, height: u64, round: u64, meta: &MsgMeta) -> FrameResult<()> {
+        let mut trace = self.0.borrow_mut();
+        let poll = Poll {
+            seq: trace.seq,
+            timestamp: meta.timestamp,
+            step: meta.step,
+            suite: meta.suite.clone(),
+            height,
+            round,
+        };
+        trace.polls.push(poll);
+        trace.seq += 1;
+        Ok(())
+    }
+}
+
+/// A JSON Lines storage of one run, writing the lines of `export_jsonl`, which `import_jsonl`
+/// loads into a database. The file is truncated when opened, so it never mixes runs. A run
+/// header is written again whenever a seed is set or a suite is added. The run ID is always
+/// 0.
+pub struct JsonlStorage {
+    writer: RefCell<BufWriter<File>>,
+    seq: Cell<u64>,
+    header: RefCell<RunHeader>,
+}
+
+impl JsonlStorage {
+    /// A function to create the file, or truncate it if exists.
+    pub fn new(path: &str) -> FrameResult<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(JsonlStorage {
+            writer: RefCell::new(BufWriter::new(file)),
+            seq: Cell::new(0),
+            header: RefCell::new(RunHeader::new(None, None, Vec::new())),
+        })
+    }
+
+    fn write(&self, mut event: TraceEvent) -> FrameResult<()> {
+        event.seq = self.seq.get();
+        self.write_line(&event)?;
+        self.seq.set(event.seq + 1);
+        Ok(())
+    }
+
+    fn write_header(&self) -> FrameResult<()> {
+        self.write_line(&*self.header.borrow())
+    }
+
+    fn write_line<S: Serialize>(&self, line: &S) -> FrameResult<()> {
+        let mut writer = self.writer.borrow_mut();
+        serde_json::to_writer(&mut *writer, line)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+impl Storage for JsonlStorage {
+    fn run_id(&self) -> i64 {
+        0
+    }
+
+    fn set_seed(&self, seed: u64) -> FrameResult<()> {
+        self.header.borrow_mut().seed = Some(seed);
+        self.write_header()
+    }
+
+    fn set_case_seed(&self, seed: u64) -> FrameResult<()> {
+        self.header.borrow_mut().case_seed = Some(seed);
+        self.write_header()
+    }
+
+    fn add_suite(&self, suite: &str) -> FrameResult<()> {
+        self.header.borrow_mut().suites.push(suite.to_string());
+        self.write_header()
+    }
+
+    fn insert(&self, msg: Msg, meta: &MsgMeta) -> FrameResult<()> {
+        self.write(TraceEvent::from_msg(0, msg, meta))
+    }
+
+    fn insert_poll(&self, height: u64, round: u64, meta: &MsgMeta) -> FrameResult<()> {
+        self.write(TraceEvent::from_poll(0, height, round, meta))
+    }
+
+    fn flush(&self) -> FrameResult<()> {
+        self.writer.borrow_mut().flush()?;
+        Ok(())
+    }
+}
+
+/// A storage which records nothing, for fast local runs. The run ID is always 0.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopStorage;
+
+impl Storage for NoopStorage {
+    fn run_id(&self) -> i64 {
+        0
+    }
+
+    fn set_seed(&self, _seed: u64) -> FrameResult<()> {
+        Ok(())
+    }
+
+    fn set_case_seed(&self, _seed: u64) -> FrameResult<()> {
+        Ok(())
+    }
+
+    fn add_suite(&self, _suite: &str) -> FrameResult<()> {
+        Ok(())
+    }
+
+    fn insert(&self, _msg: Msg, _meta: &MsgMeta) -> FrameResult<()> {
+        Ok(())
+    }
+
+    fn insert_poll(&self, _height: u64, _round: u64, _meta: &MsgMeta) -> FrameResult<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testkit::ReferenceNode;
+    use crate::whitebox::{
+        actuator::Actuator,
+        collection::{
+            export::{export_jsonl, import_jsonl},
+            query::{Filter, TraceReader},
+            storage::remove_db,
+        },
+        correctness::test_case::lock_proposal,
+    };
+    use rand::random;
+
+    #[test]
+    fn test_backends() {
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let run = |storage: Box<dyn Storage>| {
+            let node = ReferenceNode::new(authority_list.clone());
+            let mut actuator =
+                Actuator::with_storage(node, 0, 0, authority_list.clone(), storage).unwrap();
+            actuator.proc_test(lock_proposal()).unwrap();
+        };
+        run(Box::new(NoopStorage));
+
+        let memory = MemoryStorage::new();
+        run(Box::new(memory.clone()));
+        let records = memory.records();
+        assert!(!records.is_empty());
+        let mut seqs: Vec<u64> = records.iter().map(|r| r.seq).collect();
+        seqs.extend(memory.polls().iter().map(|p| p.seq));
+        seqs.sort_unstable();
+        assert_eq!(seqs, (0..seqs.len() as u64).collect::<Vec<_>>());
+
+        // the JSON Lines file loads into the database as written, a file of a run
+        let name = format!("bft_backend_{}", random::<u64>());
+        let jsonl = std::env::temp_dir().join(format!("{}.jsonl", name));
+        let db = std::env::temp_dir().join(format!("{}.db", name));
+        let (jsonl, db) = (jsonl.to_str().unwrap(), db.to_str().unwrap());
+        for _ in 0..2 {
+            let storage = JsonlStorage::new(jsonl).unwrap();
+            storage.set_seed(3).unwrap();
+            storage.set_case_seed(4).unwrap();
+            storage.add_suite("lock proposal").unwrap();
+            run(Box::new(storage));
+        }
+        let written = std::fs::read(jsonl).unwrap();
+        let run_id = import_jsonl(db, written.as_slice()).unwrap();
+        let reader = TraceReader::open(db).unwrap();
+        let info = reader.runs().unwrap().pop().unwrap();
+        assert_eq!((info.seed, info.case_seed), (Some(3), Some(4)));
+        assert_eq!(info.suites, vec!["lock proposal".to_string()]);
+        assert!(!reader.load(run_id, &Filter::default()).unwrap().is_empty());
+        let mut exported = Vec::new();
+        export_jsonl(&reader, run_id, &mut exported).unwrap();
+        let events = |lines: &[u8]| -> Vec<String> {
+            String::from_utf8(lines.to_vec())
+                .unwrap()
+                .lines()
+                .filter(|l| !l.starts_with("{\"type\":\"run\""))
+                .map(|l| l.to_string())
+                .collect()
+        };
+        assert_eq!(events(&exported), events(&written));
+        let starts = events(&written)
+            .iter()
+            .filter(|l| l.starts_with("{\"seq\":0,"))
+            .count();
+        assert_eq!(starts, 1);
+        let _ = std::fs::remove_file(jsonl);
+        remove_db(db);
+    }
+}
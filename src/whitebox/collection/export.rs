@@ -0,0 +1,428 @@
+use crate::whitebox::{
+    collection::{
+        query::{Filter, MsgKind, RunInfo, TraceReader},
+        storage::{Direction, MsgMeta, SqliteStorage, Storage},
+        util::Msg,
+    },
+    error::FrameError,
+    observer::Step,
+    FrameResult,
+};
+use serde_derive::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use time::Timespec;
+
+/// A recorded message or commit poll as a line of JSON Lines.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// Sequence number in the run.
+    pub seq: u64,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: i64,
+    /// Whether the message is sent to or received from the node.
+    pub direction: Direction,
+    /// Step of the actuator when recorded.
+    pub step: Option<Step>,
+    /// Suite running when recorded.
+    pub suite: Option<String>,
+    /// Kind of the message in lower case, or `poll` for a commit poll which gets nothing.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Height of the message or poll.
+    pub height: u64,
+    /// Round of the proposal, vote or poll.
+    pub round: Option<u64>,
+    /// The message, `None` for a commit poll.
+    pub msg: Option<Msg>,
+}
+
+/// The seeds and suites of a run as a line of JSON Lines, of type `run`. It is the first line
+/// written by `export_jsonl`, and `JsonlStorage` writes it again whenever they change.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RunHeader {
+    /// Always `run`, to tell the header from the events.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Seed of the message delivery if set.
+    pub seed: Option<u64>,
+    /// Seed of the random test cases if generated.
+    #[serde(default)]
+    pub case_seed: Option<u64>,
+    /// Names of the suites run.
+    pub suites: Vec<String>,
+}
+
+impl RunHeader {
+    pub(crate) fn new(seed: Option<u64>, case_seed: Option<u64>, suites: Vec<String>) -> Self {
+        RunHeader {
+            kind: RUN_HEADER.to_string(),
+            seed,
+            case_seed,
+            suites,
+        }
+    }
+}
+
+const RUN_HEADER: &str = "run";
+
+impl TraceEvent {
+    pub(crate) fn from_msg(seq: u64, msg: Msg, meta: &MsgMeta) -> Self {
+        let (height, round) = height_round(&msg);
+        TraceEvent {
+            seq,
+            timestamp: to_millis(meta.timestamp),
+            direction: meta.direction,
+            step: meta.step,
+            suite: meta.suite.clone(),
+            kind: kind_name(msg.kind()).to_string(),
+            height,
+            round,
+            msg: Some(msg),
+        }
+    }
+
+    pub(crate) fn from_poll(seq: u64, height: u64, round: u64, meta: &MsgMeta) -> Self {
+        TraceEvent {
+            seq,
+            timestamp: to_millis(meta.timestamp),
+            direction: meta.direction,
+            step: meta.step,
+            suite: meta.suite.clone(),
+            kind: "poll".to_string(),
+            height,
+            round: Some(round),
+            msg: None,
+        }
+    }
+}
+
+fn to_millis(t: Timespec) -> i64 {
+    t.sec * 1000 + i64::from(t.nsec) / 1_000_000
+}
+
+fn from_millis(ms: i64) -> Timespec {
+    Timespec::new(
+        ms.div_euclid(1000),
+        (ms.rem_euclid(1000) * 1_000_000) as i32,
+    )
+}
+
+fn kind_name(kind: MsgKind) -> &'static str {
+    match kind {
+        MsgKind::Proposal => "proposal",
+        MsgKind::Vote => "vote",
+        MsgKind::Commit => "commit",
+        MsgKind::Feed => "feed",
+        MsgKind::Status => "status",
+    }
+}
+
+fn height_round(msg: &Msg) -> (u64, Option<u64>) {
+    match msg {
+        Msg::Proposal(p) => (p.height, Some(p.round)),
+        Msg::Vote(v) => (v.height, Some(v.round)),
+        Msg::Commit(c) => (c.height, None),
+        Msg::Feed(f) => (f.height, None),
+        Msg::Status(s) => (s.height, None),
+    }
+}
+
+/// A function to write the run as JSON Lines, a `RunHeader` and then the messages and commit
+/// polls, one event per line in the recorded order.
+pub fn export_jsonl<W: Write>(reader: &TraceReader, run_id: i64, mut out: W) -> FrameResult<()> {
+    if let Some(RunInfo {
+        seed,
+        case_seed,
+        suites,
+        ..
+    }) = reader.runs()?.into_iter().find(|r| r.run_id == run_id)
+    {
+        serde_json::to_writer(&mut out, &RunHeader::new(seed, case_seed, suites))?;
+        out.write_all(b"\n")?;
+    }
+    let mut events = Vec::new();
+    for record in reader.load(run_id, &Filter::default())?.into_iter() {
+        let meta = MsgMeta {
+            timestamp: record.timestamp,
+            direction: record.direction,
+            step: record.step,
+            suite: record.suite,
+        };
+        events.push(TraceEvent::from_msg(record.seq, record.msg, &meta));
+    }
+    for poll in reader.polls(run_id)?.into_iter() {
+        let meta = MsgMeta {
+            timestamp: poll.timestamp,
+            direction: Direction::Recv,
+            step: poll.step,
+            suite: poll.suite,
+        };
+        events.push(TraceEvent::from_poll(
+            poll.seq,
+            poll.height,
+            poll.round,
+            &meta,
+        ));
+    }
+    events.sort_by_key(|e| e.seq);
+
+    for event in events.iter() {
+        serde_json::to_writer(&mut out, event)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// A function to import JSON Lines written by `export_jsonl` as a new run of the database,
+/// return the run ID. The seeds and suites of the run headers are recorded, and the events in
+/// the order of the lines with new sequence numbers. A commit poll without a round is a decode
+/// error.
+pub fn import_jsonl<R: BufRead>(db_path: &str, input: R) -> FrameResult<i64> {
+    let storage = SqliteStorage::new(db_path)?;
+    let mut suites: Vec<String> = Vec::new();
+    let mut add_suite = |suite: &String| -> FrameResult<()> {
+        if !suites.contains(suite) {
+            storage.add_suite(suite)?;
+            suites.push(suite.clone());
+        }
+        Ok(())
+    };
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: serde_json::Value = serde_json::from_str(&line)?;
+        if value["type"] == RUN_HEADER {
+            let header: RunHeader = serde_json::from_value(value)?;
+            if let Some(seed) = header.seed {
+                storage.set_seed(seed)?;
+            }
+            if let Some(seed) = header.case_seed {
+                storage.set_case_seed(seed)?;
+            }
+            for suite in header.suites.iter() {
+                add_suite(suite)?;
+            }
+            continue;
+        }
+        let event: TraceEvent = serde_json::from_value(value)?;
+        if let Some(suite) = event.suite.as_ref() {
+            add_suite(suite)?;
+        }
+        let meta = MsgMeta {
+            timestamp: from_millis(event.timestamp),
+            direction: event.direction,
+            step: event.step,
+            suite: event.suite,
+        };
+        match (event.msg, event.round) {
+            (Some(msg), _) => storage.insert(msg, &meta)?,
+            (None, Some(round)) => storage.insert_poll(event.height, round, &meta)?,
+            (None, None) => {
+                let err = serde::de::Error::custom(format!("poll {} without a round", event.seq));
+                return Err(FrameError::Decode(err));
+            }
+        }
+    }
+    // the last batch is written here rather than when the storage is dropped
+    storage.flush()?;
+    Ok(storage.run_id())
+}
+
+const CSV_HEAD: &str = "seq,timestamp,direction,step,suite,height";
+
+fn csv_columns(kind: MsgKind) -> &'static str {
+    match kind {
+        MsgKind::Proposal => "round,content,proposer,lock_round,lock_votes,signature",
+        MsgKind::Vote => "round,vote_type,proposal,voter,signature",
+        MsgKind::Commit => "node,result,proof",
+        MsgKind::Feed => "proposal",
+        MsgKind::Status => "authority_list",
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// quote the field if it has a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_values(msg: &Msg) -> Vec<String> {
+    match msg {
+        Msg::Proposal(p) => vec![
+            p.round.to_string(),
+            hex(&p.content),
+            hex(&p.proposer),
+            p.lock_round.map(|r| r.to_string()).unwrap_or_default(),
+            p.lock_votes.len().to_string(),
+            hex(&p.signature),
+        ],
+        Msg::Vote(v) => vec![
+            v.round.to_string(),
+            format!("{:?}", v.vote_type),
+            hex(&v.proposal),
+            hex(&v.voter),
+            hex(&v.signature),
+        ],
+        Msg::Commit(c) => vec![
+            c.node.to_string(),
+            hex(&c.result),
+            c.proof.len().to_string(),
+        ],
+        Msg::Feed(f) => vec![hex(&f.proposal)],
+        Msg::Status(s) => vec![s
+            .authority_list
+            .iter()
+            .map(|a| hex(a))
+            .collect::<Vec<_>>()
+            .join(";")],
+    }
+}
+
+/// A function to write the messages of a kind in the run as CSV with a header, one row per
+/// message in the recorded order. Bytes are written in hex, authorities of a status are
+/// separated by `;`, and lock votes of a proposal and the proof of a commit are counted.
+pub fn export_csv<W: Write>(
+    reader: &TraceReader,
+    run_id: i64,
+    kind: MsgKind,
+    mut out: W,
+) -> FrameResult<()> {
+    writeln!(out, "{},{}", CSV_HEAD, csv_columns(kind))?;
+    let filter = Filter {
+        kind: Some(kind),
+        ..Filter::default()
+    };
+    for record in reader.load(run_id, &filter)?.into_iter() {
+        let mut row = vec![
+            record.seq.to_string(),
+            to_millis(record.timestamp).to_string(),
+            record.direction.as_str().to_string(),
+            record.step.map(|s| format!("{:?}", s)).unwrap_or_default(),
+            record.suite.clone().unwrap_or_default(),
+            height_round(&record.msg).0.to_string(),
+        ];
+        row.append(&mut csv_values(&record.msg));
+        let row: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+        writeln!(out, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testkit::ReferenceNode;
+    use crate::whitebox::{
+        actuator::Actuator,
+        collection::storage::{remove_db, Direction},
+        correctness::test_case::lock_proposal,
+        scheduler::Delivery,
+        Vote, VoteType,
+    };
+    use rand::random;
+
+    #[test]
+    fn test_export_import() {
+        let path = std::env::temp_dir().join(format!("bft_export_{}.db", random::<u64>()));
+        let path = path.to_str().unwrap();
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let node = ReferenceNode::new(authority_list.clone());
+        let mut actuator = Actuator::new(node, 0, 0, authority_list, path).unwrap();
+        actuator.set_delivery(Delivery::new(9)).unwrap();
+        actuator.proc_test(lock_proposal()).unwrap();
+        let run_id = actuator.run_id();
+        let reader = TraceReader::open(path).unwrap();
+
+        let mut jsonl = Vec::new();
+        export_jsonl(&reader, run_id, &mut jsonl).unwrap();
+        let lines = String::from_utf8(jsonl.clone()).unwrap();
+        let records = reader.load(run_id, &Filter::default()).unwrap();
+        let polls = reader.polls(run_id).unwrap();
+        assert_eq!(lines.lines().count(), records.len() + polls.len() + 1);
+        let header: RunHeader = serde_json::from_str(lines.lines().next().unwrap()).unwrap();
+        assert_eq!(header.seed, Some(9));
+
+        // the imported run is the same but the run ID
+        let imported = import_jsonl(path, jsonl.as_slice()).unwrap();
+        assert_ne!(imported, run_id);
+        let mut again = Vec::new();
+        export_jsonl(&reader, imported, &mut again).unwrap();
+        assert_eq!(again, jsonl);
+        let runs = reader.runs().unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[1].seed, Some(9));
+
+        // a poll without a round is not imported as round 0
+        let poll = lines
+            .lines()
+            .find(|l| l.contains("\"type\":\"poll\""))
+            .unwrap();
+        let mut event: TraceEvent = serde_json::from_str(poll).unwrap();
+        event.round = None;
+        let line = serde_json::to_string(&event).unwrap();
+        match import_jsonl(path, line.as_bytes()) {
+            Err(FrameError::Decode(_)) => (),
+            res => panic!("Unexpected result {:?}", res),
+        }
+
+        let mut csv = Vec::new();
+        export_csv(&reader, run_id, MsgKind::Vote, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let votes = records.iter().filter(|r| r.msg.kind() == MsgKind::Vote);
+        assert_eq!(csv.lines().count(), votes.count() + 1);
+        assert!(csv.starts_with("seq,timestamp,direction,step,suite,height,round,vote_type"));
+        remove_db(path);
+    }
+
+    #[test]
+    fn test_import_flush() {
+        let path = std::env::temp_dir().join(format!("bft_import_{}.db", random::<u64>()));
+        let path = path.to_str().unwrap();
+        let meta = MsgMeta {
+            timestamp: time::get_time(),
+            direction: Direction::Send,
+            step: None,
+            suite: None,
+        };
+        let mut jsonl = String::new();
+        for voter in 0..3 {
+            let vote = Vote {
+                height: 1,
+                round: 0,
+                vote_type: VoteType::Prevote,
+                proposal: vec![1],
+                voter: vec![voter],
+                signature: Vec::new(),
+            };
+            let event = TraceEvent::from_msg(u64::from(voter), Msg::Vote(vote), &meta);
+            jsonl.push_str(&serde_json::to_string(&event).unwrap());
+            jsonl.push('\n');
+        }
+
+        // fewer events than a batch are written when imported
+        let run_id = import_jsonl(path, jsonl.as_bytes()).unwrap();
+        let reader = TraceReader::open(path).unwrap();
+        assert_eq!(reader.load(run_id, &Filter::default()).unwrap().len(), 3);
+
+        // a failed write is returned rather than lost when the storage is dropped
+        let conn = rusqlite::Connection::open(path).unwrap();
+        conn.execute_batch(
+            "CREATE TRIGGER no_vote BEFORE INSERT ON vote BEGIN SELECT RAISE(ABORT, 'no vote'); END;",
+        )
+        .unwrap();
+        match import_jsonl(path, jsonl.as_bytes()) {
+            Err(FrameError::Storage(_)) => (),
+            res => panic!("Unexpected result {:?}", res),
+        }
+        remove_db(path);
+    }
+}
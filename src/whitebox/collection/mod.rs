@@ -1,6 +1,14 @@
-///
+/// Storage backends other than SQLite.
+pub mod backend;
+/// Exporting a run as JSON Lines or CSV.
+pub mod export;
+/// Querying the recorded runs.
+pub mod query;
+/// The SQLite schema and its migrations.
+pub mod schema;
+/// Recording the messages of a run.
 pub mod storage;
-///
+/// Types shared by the storage.
 pub mod util;
-///
+/// Votes received in the recent heights.
 pub mod vote_cache;
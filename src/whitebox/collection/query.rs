@@ -0,0 +1,395 @@
+use crate::whitebox::{
+    collection::{schema, storage::Direction, util::Msg},
+    error::FrameError,
+    observer::Step,
+    FrameResult, VoteType,
+};
+use rusqlite::{types::ToSql, Connection, OpenFlags, Row, NO_PARAMS};
+use time::Timespec;
+
+/// A run recorded in the storage.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunInfo {
+    /// ID of the run, in the order the runs start.
+    pub run_id: i64,
+    /// When the run starts.
+    pub start_time: Timespec,
+    /// Seed of the message delivery if set.
+    pub seed: Option<u64>,
+    /// Seed of the random test cases if generated.
+    pub case_seed: Option<u64>,
+    /// Names of the suites run.
+    pub suites: Vec<String>,
+    /// Version of the test framework.
+    pub version: String,
+}
+
+/// Kind of a recorded message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MsgKind {
+    /// Proposals.
+    Proposal,
+    /// Prevotes and precommits.
+    Vote,
+    /// Commits of the node.
+    Commit,
+    /// Feeds to the node.
+    Feed,
+    /// Status sent to the node.
+    Status,
+}
+
+impl MsgKind {
+    /// A function to get all the kinds.
+    pub fn all() -> Vec<MsgKind> {
+        vec![
+            MsgKind::Proposal,
+            MsgKind::Vote,
+            MsgKind::Commit,
+            MsgKind::Feed,
+            MsgKind::Status,
+        ]
+    }
+
+    // table, payload column and whether there is a round column
+    fn table(self) -> (&'static str, &'static str, bool) {
+        match self {
+            MsgKind::Proposal => ("proposal", "proposal", true),
+            MsgKind::Vote => ("vote", "vote", true),
+            MsgKind::Commit => ("cmt", "cmt", false),
+            MsgKind::Feed => ("feed", "feed", false),
+            MsgKind::Status => ("status", "status", false),
+        }
+    }
+
+    fn decode(self, payload: &str) -> FrameResult<Msg> {
+        let msg = match self {
+            MsgKind::Proposal => Msg::Proposal(serde_json::from_str(payload)?),
+            MsgKind::Vote => Msg::Vote(serde_json::from_str(payload)?),
+            MsgKind::Commit => Msg::Commit(serde_json::from_str(payload)?),
+            MsgKind::Feed => Msg::Feed(serde_json::from_str(payload)?),
+            MsgKind::Status => Msg::Status(serde_json::from_str(payload)?),
+        };
+        Ok(msg)
+    }
+}
+
+impl Msg {
+    /// A function to get the kind of the message.
+    pub fn kind(&self) -> MsgKind {
+        match self {
+            Msg::Proposal(_) => MsgKind::Proposal,
+            Msg::Vote(_) => MsgKind::Vote,
+            Msg::Commit(_) => MsgKind::Commit,
+            Msg::Feed(_) => MsgKind::Feed,
+            Msg::Status(_) => MsgKind::Status,
+        }
+    }
+}
+
+/// A filter of recorded messages, `None` matches everything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Height of the message.
+    pub height: Option<u64>,
+    /// Messages without a round, commits, feeds and status, never match a round.
+    pub round: Option<u64>,
+    /// Kind of the message.
+    pub kind: Option<MsgKind>,
+    /// Only votes have a voter, so other messages never match a voter.
+    pub voter: Option<Vec<u8>>,
+    /// Whether the message is sent to or received from the node.
+    pub direction: Option<Direction>,
+    /// Only votes have a vote type, so other messages never match a vote type.
+    pub vote_type: Option<VoteType>,
+    /// Only proposals have a proposer, so other messages never match a proposer.
+    pub proposer: Option<Vec<u8>>,
+}
+
+/// A recorded message with where it is recorded from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// Sequence number in the run, in the order the messages are recorded.
+    pub seq: u64,
+    /// When the message is recorded.
+    pub timestamp: Timespec,
+    /// Whether the message is sent to or received from the node.
+    pub direction: Direction,
+    /// Step of the actuator when recorded.
+    pub step: Option<Step>,
+    /// Suite running when recorded.
+    pub suite: Option<String>,
+    /// The recorded message.
+    pub msg: Msg,
+}
+
+/// A commit poll of the actuator which gets nothing from the node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Poll {
+    /// Sequence number in the run, shared with the recorded messages.
+    pub seq: u64,
+    /// When the commit is polled.
+    pub timestamp: Timespec,
+    /// Step of the actuator when polled.
+    pub step: Option<Step>,
+    /// Suite running when polled.
+    pub suite: Option<String>,
+    /// Height of the actuator when polled.
+    pub height: u64,
+    /// Round of the actuator when polled.
+    pub round: u64,
+}
+
+/// A reader of the runs and messages recorded by actuators.
+pub struct TraceReader(Connection);
+
+impl TraceReader {
+    /// A function to open the database the actuators record in as read-only. A database of
+    /// another schema version is reported as `FrameError::SchemaVersion` rather than migrated.
+    pub fn open(db_path: &str) -> FrameResult<Self> {
+        let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        schema::check(&conn)?;
+        Ok(TraceReader(conn))
+    }
+
+    /// A function to get all the runs in the database.
+    pub fn runs(&self) -> FrameResult<Vec<RunInfo>> {
+        let mut stmt = self.0.prepare(
+            "SELECT run_id, start_time, seed, case_seed, suites, version FROM runs
+                ORDER BY run_id",
+        )?;
+        let rows = stmt.query_map(NO_PARAMS, |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get::<_, Option<i64>>(2)?,
+                row.get::<_, Option<i64>>(3)?,
+                row.get::<_, String>(4)?,
+                row.get(5)?,
+            ))
+        })?;
+        let mut res = Vec::new();
+        for row in rows {
+            let (run_id, start_time, seed, case_seed, suites, version) = row?;
+            res.push(RunInfo {
+                run_id,
+                start_time,
+                seed: seed.map(|s| s as u64),
+                case_seed: case_seed.map(|s| s as u64),
+                suites: serde_json::from_str(&suites)?,
+                version,
+            });
+        }
+        Ok(res)
+    }
+
+    /// A function to load the messages of the run matched by the filter, in the order they
+    /// are recorded.
+    pub fn load(&self, run_id: i64, filter: &Filter) -> FrameResult<Vec<Record>> {
+        let mut res = Vec::new();
+        for kind in MsgKind::all().into_iter() {
+            if filter.kind.is_some_and(|k| k != kind) {
+                continue;
+            }
+            res.append(&mut self.load_kind(run_id, kind, filter)?);
+        }
+        res.sort_by_key(|r| r.seq);
+        Ok(res)
+    }
+
+    /// A function to get the commit polls in the run which get nothing from the node.
+    pub fn polls(&self, run_id: i64) -> FrameResult<Vec<Poll>> {
+        let mut stmt = self.0.prepare(
+            "SELECT seq, timestamp, step, suite, height, round FROM poll
+                WHERE run_id = ?1 ORDER BY seq",
+        )?;
+        let rows = stmt.query_map(&[&run_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, i64>(5)?,
+            ))
+        })?;
+        let mut res = Vec::new();
+        for row in rows {
+            let (seq, timestamp, step, suite, height, round) = row?;
+            res.push(Poll {
+                seq: seq as u64,
+                timestamp,
+                step: decode_step(step)?,
+                suite,
+                height: height as u64,
+                round: round as u64,
+            });
+        }
+        Ok(res)
+    }
+
+    fn load_kind(&self, run_id: i64, kind: MsgKind, filter: &Filter) -> FrameResult<Vec<Record>> {
+        let (table, payload, has_round) = kind.table();
+        if (filter.round.is_some() && !has_round)
+            || ((filter.voter.is_some() || filter.vote_type.is_some()) && kind != MsgKind::Vote)
+            || (filter.proposer.is_some() && kind != MsgKind::Proposal)
+        {
+            return Ok(Vec::new());
+        }
+
+        let mut sql = format!(
+            "SELECT seq, timestamp, direction, step, suite, {} FROM {} WHERE run_id = ?",
+            payload, table
+        );
+        let mut params: Vec<Box<dyn ToSql>> = vec![Box::new(run_id)];
+        if let Some(height) = filter.height {
+            sql.push_str(" AND height = ?");
+            params.push(Box::new(height as i64));
+        }
+        if let Some(round) = filter.round {
+            sql.push_str(" AND round = ?");
+            params.push(Box::new(round as i64));
+        }
+        if let Some(voter) = filter.voter.as_ref() {
+            sql.push_str(" AND voter = ?");
+            params.push(Box::new(voter.clone()));
+        }
+        if let Some(vote_type) = filter.vote_type.as_ref() {
+            sql.push_str(" AND vote_type = ?");
+            params.push(Box::new(format!("{:?}", vote_type)));
+        }
+        if let Some(proposer) = filter.proposer.as_ref() {
+            sql.push_str(" AND proposer = ?");
+            params.push(Box::new(proposer.clone()));
+        }
+        if let Some(direction) = filter.direction {
+            sql.push_str(" AND direction = ?");
+            params.push(Box::new(direction.as_str()));
+        }
+
+        let mut stmt = self.0.prepare(&sql)?;
+        let rows = stmt.query_map(params.iter().map(|p| p.as_ref()), read_row)?;
+        let mut res = Vec::new();
+        for row in rows {
+            let (seq, timestamp, direction, step, suite, payload) = row?;
+            res.push(Record {
+                seq: seq as u64,
+                timestamp,
+                direction: Direction::from_name(&direction)
+                    .ok_or_else(|| unknown_name("direction", &direction))?,
+                step: decode_step(step)?,
+                suite,
+                msg: kind.decode(&payload)?,
+            });
+        }
+        Ok(res)
+    }
+}
+
+fn decode_step(step: Option<String>) -> FrameResult<Option<Step>> {
+    match step {
+        Some(name) => Step::from_name(&name)
+            .map(Some)
+            .ok_or_else(|| unknown_name("step", &name)),
+        None => Ok(None),
+    }
+}
+
+fn unknown_name(column: &str, name: &str) -> FrameError {
+    FrameError::Decode(serde::de::Error::custom(format!(
+        "unknown {} {:?}",
+        column, name
+    )))
+}
+
+type RawRow = (
+    i64,
+    Timespec,
+    String,
+    Option<String>,
+    Option<String>,
+    String,
+);
+
+fn read_row(row: &Row) -> rusqlite::Result<RawRow> {
+    Ok((
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get(4)?,
+        row.get(5)?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testkit::ReferenceNode;
+    use crate::whitebox::{
+        actuator::Actuator, collection::storage::remove_db,
+        correctness::test_case::no_byzantine_cases,
+    };
+    use rand::random;
+
+    #[test]
+    fn test_query() {
+        let path = std::env::temp_dir().join(format!("bft_query_{}.db", random::<u64>()));
+        let path = path.to_str().unwrap();
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let node = ReferenceNode::new(authority_list.clone());
+        let mut actuator = Actuator::new(node, 0, 0, authority_list.clone(), path).unwrap();
+        actuator.proc_test(no_byzantine_cases()).unwrap();
+        let run_id = actuator.run_id();
+
+        let reader = TraceReader::open(path).unwrap();
+        let runs = reader.runs().unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].run_id, run_id);
+
+        // messages and polls are in a total order
+        let all = reader.load(run_id, &Filter::default()).unwrap();
+        let mut seqs: Vec<u64> = all.iter().map(|r| r.seq).collect();
+        seqs.extend(reader.polls(run_id).unwrap().iter().map(|p| p.seq));
+        seqs.sort_unstable();
+        assert_eq!(seqs, (0..seqs.len() as u64).collect::<Vec<_>>());
+
+        // every height commits once with what the node received
+        let commits = Filter {
+            kind: Some(MsgKind::Commit),
+            ..Filter::default()
+        };
+        let commits = reader.load(run_id, &commits).unwrap();
+        assert!(!commits.is_empty());
+        assert!(commits.iter().all(|r| r.direction == Direction::Recv));
+
+        let filter = Filter {
+            height: Some(1),
+            round: Some(0),
+            voter: Some(authority_list[0].clone()),
+            ..Filter::default()
+        };
+        let votes = reader.load(run_id, &filter).unwrap();
+        assert_eq!(votes.len(), 2);
+        for record in votes.iter() {
+            match &record.msg {
+                Msg::Vote(v) => assert_eq!((v.height, v.round), (1, 0)),
+                m => panic!("Unexpected message {:?}", m),
+            }
+            assert_eq!(record.direction, Direction::Recv);
+        }
+
+        // an unknown direction is an error rather than a sent message
+        let conn = Connection::open(path).unwrap();
+        conn.execute(
+            "UPDATE vote SET direction = 'sideways' WHERE rowid = (SELECT MIN(rowid) FROM vote)",
+            NO_PARAMS,
+        )
+        .unwrap();
+        match reader.load(run_id, &Filter::default()) {
+            Err(FrameError::Decode(_)) => (),
+            res => panic!("Unexpected result {:?}", res.map(|r| r.len())),
+        }
+        remove_db(path);
+    }
+}
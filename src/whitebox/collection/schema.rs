@@ -0,0 +1,555 @@
+use crate::whitebox::{
+    collection::storage::Direction, error::FrameError, Address, Commit, FrameResult, Proposal,
+    Status, Vote,
+};
+use rusqlite::{params, Connection, NO_PARAMS};
+use time::Timespec;
+
+/// Version of the schema the framework writes. Databases of older versions are migrated
+/// forward when opened to record in.
+pub const SCHEMA_VERSION: u32 = 3;
+
+type Migration = fn(&Connection) -> FrameResult<()>;
+
+// the migration from every version to the next one
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] =
+    [create_tables, normalize_columns, add_case_seed];
+
+// the tables of the recorded runs, messages are only kept as JSON
+const TABLES_V1: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    run_id      INTEGER PRIMARY KEY AUTOINCREMENT,
+    start_time  TEXT NOT NULL,
+    seed        INTEGER,
+    suites      TEXT NOT NULL,
+    version     TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS proposal (
+    run_id      INTEGER NOT NULL,
+    seq         INTEGER NOT NULL,
+    timestamp   TEXT NOT NULL,
+    direction   TEXT NOT NULL,
+    step        TEXT,
+    suite       TEXT,
+    height      INTEGER NOT NULL,
+    round       INTEGER NOT NULL,
+    proposal    TEXT NOT NULL,
+    PRIMARY KEY (run_id, seq)
+);
+CREATE TABLE IF NOT EXISTS vote (
+    run_id      INTEGER NOT NULL,
+    seq         INTEGER NOT NULL,
+    timestamp   TEXT NOT NULL,
+    direction   TEXT NOT NULL,
+    step        TEXT,
+    suite       TEXT,
+    height      INTEGER NOT NULL,
+    round       INTEGER NOT NULL,
+    voter       BLOB NOT NULL,
+    vote        TEXT NOT NULL,
+    PRIMARY KEY (run_id, seq)
+);
+CREATE TABLE IF NOT EXISTS cmt (
+    run_id      INTEGER NOT NULL,
+    seq         INTEGER NOT NULL,
+    timestamp   TEXT NOT NULL,
+    direction   TEXT NOT NULL,
+    step        TEXT,
+    suite       TEXT,
+    height      INTEGER NOT NULL,
+    cmt         TEXT NOT NULL,
+    PRIMARY KEY (run_id, seq)
+);
+CREATE TABLE IF NOT EXISTS feed (
+    run_id      INTEGER NOT NULL,
+    seq         INTEGER NOT NULL,
+    timestamp   TEXT NOT NULL,
+    direction   TEXT NOT NULL,
+    step        TEXT,
+    suite       TEXT,
+    height      INTEGER NOT NULL,
+    feed        TEXT NOT NULL,
+    PRIMARY KEY (run_id, seq)
+);
+CREATE TABLE IF NOT EXISTS status (
+    run_id      INTEGER NOT NULL,
+    seq         INTEGER NOT NULL,
+    timestamp   TEXT NOT NULL,
+    direction   TEXT NOT NULL,
+    step        TEXT,
+    suite       TEXT,
+    height      INTEGER NOT NULL,
+    status      TEXT NOT NULL,
+    PRIMARY KEY (run_id, seq)
+);
+-- commit polls of the node which get nothing
+CREATE TABLE IF NOT EXISTS poll (
+    run_id      INTEGER NOT NULL,
+    seq         INTEGER NOT NULL,
+    timestamp   TEXT NOT NULL,
+    direction   TEXT NOT NULL,
+    step        TEXT,
+    suite       TEXT,
+    height      INTEGER NOT NULL,
+    round       INTEGER NOT NULL,
+    PRIMARY KEY (run_id, seq)
+);
+";
+
+// key fields of the messages as columns, and indexes of the common queries
+const COLUMNS_V2: &str = "
+ALTER TABLE proposal ADD COLUMN content BLOB;
+ALTER TABLE proposal ADD COLUMN proposer BLOB;
+ALTER TABLE proposal ADD COLUMN lock_round INTEGER;
+ALTER TABLE vote ADD COLUMN vote_type TEXT;
+ALTER TABLE vote ADD COLUMN proposal BLOB;
+ALTER TABLE cmt ADD COLUMN result BLOB;
+CREATE INDEX proposal_height ON proposal (run_id, height, round);
+CREATE INDEX proposal_proposer ON proposal (proposer);
+CREATE INDEX vote_height ON vote (run_id, height, round, vote_type);
+CREATE INDEX vote_voter ON vote (voter);
+CREATE INDEX cmt_height ON cmt (run_id, height);
+CREATE INDEX poll_height ON poll (run_id, height, round);
+";
+
+// the seed of the random test cases next to the seed of the message delivery
+const CASE_SEED_V3: &str = "ALTER TABLE runs ADD COLUMN case_seed INTEGER;";
+
+// the tables before runs were recorded, keyed by the timestamp
+const BASELINE_TABLES: [&str; 5] = ["proposal", "vote", "cmt", "feed", "status"];
+
+// the framework version which wrote the tables before runs were recorded
+const BASELINE_VERSION: &str = "0.1.0";
+
+fn create_tables(conn: &Connection) -> FrameResult<()> {
+    let baseline = has_table(conn, "proposal")? && !has_column(conn, "proposal", "run_id")?;
+    if baseline {
+        for table in BASELINE_TABLES.iter() {
+            if has_table(conn, table)? {
+                conn.execute_batch(&format!("ALTER TABLE {0} RENAME TO {0}_v0", table))?;
+            }
+        }
+    }
+    conn.execute_batch(TABLES_V1)?;
+    if baseline {
+        copy_baseline(conn)?;
+    }
+    Ok(())
+}
+
+// a baseline row: timestamp, table, height, round, message as JSON and voter
+type BaselineRow = (Timespec, String, i64, Option<i64>, String, Option<Vec<u8>>);
+
+// Copy the messages before runs were recorded into a run, in the order of their timestamps.
+// The direction was not recorded, so it is taken from the node, the first authority of the
+// first status: the proposals and votes of the node and the commits are received from it.
+fn copy_baseline(conn: &Connection) -> FrameResult<()> {
+    let mut selects = Vec::new();
+    for table in BASELINE_TABLES.iter() {
+        if !has_table(conn, &format!("{}_v0", table))? {
+            continue;
+        }
+        let (round, voter) = match *table {
+            "proposal" => ("round", "NULL"),
+            "vote" => ("round", "voter"),
+            _ => ("NULL", "NULL"),
+        };
+        selects.push(format!(
+            "SELECT timestamp, '{0}', height, {1}, {0}, {2} FROM {0}_v0",
+            table, round, voter
+        ));
+    }
+    let sql = format!("{} ORDER BY timestamp", selects.join(" UNION ALL "));
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(NO_PARAMS, |row| {
+        Ok((
+            row.get(0)?,
+            row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+        ))
+    })?;
+    let mut baseline: Vec<BaselineRow> = Vec::new();
+    for row in rows {
+        baseline.push(row?);
+    }
+
+    let node: Option<Address> = match baseline.iter().find(|r| r.1 == "status") {
+        Some((.., json, _)) => serde_json::from_str::<Status>(json)?
+            .authority_list
+            .first()
+            .cloned(),
+        None => None,
+    };
+    let start_time = baseline
+        .first()
+        .map_or_else(time::get_time, |(timestamp, ..)| *timestamp);
+    conn.execute(
+        "INSERT INTO runs (start_time, seed, suites, version) VALUES (?1, NULL, '[]', ?2)",
+        params![start_time, BASELINE_VERSION],
+    )?;
+    let run_id = conn.last_insert_rowid();
+
+    for (seq, (timestamp, table, height, round, json, voter)) in baseline.into_iter().enumerate() {
+        let from_node = |address: &Address| node.as_ref() == Some(address);
+        let direction = match table.as_str() {
+            "proposal" => {
+                let p: Proposal = serde_json::from_str(&json)?;
+                if from_node(&p.proposer) {
+                    Direction::Recv
+                } else {
+                    Direction::Send
+                }
+            }
+            "vote" if voter.as_ref().is_some_and(from_node) => Direction::Recv,
+            "cmt" => Direction::Recv,
+            _ => Direction::Send,
+        };
+        let head = params![run_id, seq as i64, timestamp, direction.as_str(), height];
+        match table.as_str() {
+            "proposal" => conn.execute(
+                "INSERT INTO proposal
+                    (run_id, seq, timestamp, direction, height, round, proposal)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                head.iter().chain(params![round, json].iter()),
+            )?,
+            "vote" => conn.execute(
+                "INSERT INTO vote
+                    (run_id, seq, timestamp, direction, height, round, voter, vote)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                head.iter().chain(params![round, voter, json].iter()),
+            )?,
+            _ => conn.execute(
+                &format!(
+                    "INSERT INTO {0} (run_id, seq, timestamp, direction, height, {0})
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    table
+                ),
+                head.iter().chain(params![json].iter()),
+            )?,
+        };
+    }
+    for table in BASELINE_TABLES.iter() {
+        conn.execute_batch(&format!("DROP TABLE IF EXISTS {}_v0", table))?;
+    }
+    Ok(())
+}
+
+fn normalize_columns(conn: &Connection) -> FrameResult<()> {
+    conn.execute_batch(COLUMNS_V2)?;
+    for (run_id, seq, json) in rows(conn, "SELECT run_id, seq, proposal FROM proposal")? {
+        let p: Proposal = serde_json::from_str(&json)?;
+        conn.execute(
+            "UPDATE proposal SET content = ?1, proposer = ?2, lock_round = ?3
+                WHERE run_id = ?4 AND seq = ?5",
+            params![
+                p.content,
+                p.proposer,
+                p.lock_round.map(|r| r as i64),
+                run_id,
+                seq
+            ],
+        )?;
+    }
+    for (run_id, seq, json) in rows(conn, "SELECT run_id, seq, vote FROM vote")? {
+        let v: Vote = serde_json::from_str(&json)?;
+        conn.execute(
+            "UPDATE vote SET vote_type = ?1, proposal = ?2 WHERE run_id = ?3 AND seq = ?4",
+            params![format!("{:?}", v.vote_type), v.proposal, run_id, seq],
+        )?;
+    }
+    for (run_id, seq, json) in rows(conn, "SELECT run_id, seq, cmt FROM cmt")? {
+        let c: Commit = serde_json::from_str(&json)?;
+        conn.execute(
+            "UPDATE cmt SET result = ?1 WHERE run_id = ?2 AND seq = ?3",
+            params![c.result, run_id, seq],
+        )?;
+    }
+    Ok(())
+}
+
+fn add_case_seed(conn: &Connection) -> FrameResult<()> {
+    conn.execute_batch(CASE_SEED_V3)?;
+    Ok(())
+}
+
+fn rows(conn: &Connection, sql: &str) -> FrameResult<Vec<(i64, i64, String)>> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+    let mut res = Vec::new();
+    for row in rows {
+        res.push(row?);
+    }
+    Ok(res)
+}
+
+/// A function to get the schema version of the database without writing it, 0 for a new or
+/// unversioned one, including the tables before runs were recorded.
+pub fn version(conn: &Connection) -> FrameResult<u32> {
+    if !has_table(conn, "schema_version")? {
+        return Ok(0);
+    }
+    let version: Option<i64> = conn.query_row(
+        "SELECT MAX(version) FROM schema_version",
+        NO_PARAMS,
+        |row| row.get(0),
+    )?;
+    Ok(version.unwrap_or(0) as u32)
+}
+
+/// A function to check the database is of `SCHEMA_VERSION` without migrating it.
+pub fn check(conn: &Connection) -> FrameResult<()> {
+    let version = version(conn)?;
+    if version != SCHEMA_VERSION {
+        return Err(FrameError::SchemaVersion(version));
+    }
+    Ok(())
+}
+
+fn has_table(conn: &Connection, table: &str) -> FrameResult<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        params![table],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> FrameResult<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = stmt.query_map(NO_PARAMS, |row| row.get::<_, String>(1))?;
+    for name in names {
+        if name? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// A function to migrate the database to `SCHEMA_VERSION`, each version in a transaction.
+/// Return the version before migrated.
+pub fn migrate(conn: &Connection) -> FrameResult<u32> {
+    let from = version(conn)?;
+    if from > SCHEMA_VERSION {
+        return Err(FrameError::SchemaVersion(from));
+    }
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version     INTEGER NOT NULL,
+            applied     TEXT NOT NULL
+        )",
+        NO_PARAMS,
+    )?;
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        conn.execute_batch("BEGIN")?;
+        let res = migration(conn).and_then(|_| {
+            conn.execute(
+                "INSERT INTO schema_version (version, applied) VALUES (?1, ?2)",
+                params![version as i64 + 1, time::get_time()],
+            )?;
+            Ok(())
+        });
+        if let Err(e) = res {
+            conn.execute_batch("ROLLBACK")?;
+            return Err(e);
+        }
+        conn.execute_batch("COMMIT")?;
+    }
+    Ok(from)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::whitebox::{
+        collection::{
+            query::{Filter, MsgKind, TraceReader},
+            storage::remove_db,
+            util::Msg,
+        },
+        VoteType,
+    };
+    use rand::random;
+
+    #[test]
+    fn test_migrate() {
+        let path = std::env::temp_dir().join(format!("bft_schema_{}.db", random::<u64>()));
+        let path = path.to_str().unwrap();
+
+        // a database written before versioning
+        let conn = Connection::open(path).unwrap();
+        create_tables(&conn).unwrap();
+        let vote = Vote {
+            height: 1,
+            round: 0,
+            vote_type: VoteType::Precommit,
+            proposal: vec![1, 2, 3],
+            voter: vec![0],
+            signature: Vec::new(),
+        };
+        conn.execute(
+            "INSERT INTO runs (start_time, seed, suites, version) VALUES (?1, NULL, '[]', '0.1.0')",
+            params![time::get_time()],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO vote
+                (run_id, seq, timestamp, direction, step, suite, height, round, voter, vote)
+                VALUES (1, 0, ?1, 'send', NULL, NULL, 1, 0, ?2, ?3)",
+            params![
+                time::get_time(),
+                vote.voter,
+                serde_json::to_string(&vote).unwrap()
+            ],
+        )
+        .unwrap();
+        // the reader does not migrate it
+        match TraceReader::open(path) {
+            Err(FrameError::SchemaVersion(0)) => (),
+            _ => panic!("Open a database not migrated"),
+        }
+        assert!(!has_table(&conn, "schema_version").unwrap());
+        assert_eq!(migrate(&conn).unwrap(), 0);
+        assert_eq!(version(&conn).unwrap(), SCHEMA_VERSION);
+        assert_eq!(migrate(&conn).unwrap(), SCHEMA_VERSION);
+
+        let reader = TraceReader::open(path).unwrap();
+        let filter = Filter {
+            kind: Some(MsgKind::Vote),
+            vote_type: Some(VoteType::Precommit),
+            ..Filter::default()
+        };
+        assert_eq!(reader.load(1, &filter).unwrap().len(), 1);
+        let proposal: Vec<u8> = conn
+            .query_row("SELECT proposal FROM vote", NO_PARAMS, |row| row.get(0))
+            .unwrap();
+        assert_eq!(proposal, vote.proposal);
+
+        // a newer database is not opened
+        conn.execute(
+            "INSERT INTO schema_version (version, applied) VALUES (?1, ?2)",
+            params![SCHEMA_VERSION as i64 + 1, time::get_time()],
+        )
+        .unwrap();
+        match TraceReader::open(path) {
+            Err(FrameError::SchemaVersion(v)) => assert_eq!(v, SCHEMA_VERSION + 1),
+            _ => panic!("Open a database of a newer schema"),
+        }
+        remove_db(path);
+    }
+
+    #[test]
+    fn test_baseline() {
+        let path = std::env::temp_dir().join(format!("bft_baseline_{}.db", random::<u64>()));
+        let path = path.to_str().unwrap();
+
+        // a database written before runs were recorded
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE proposal (
+                timestamp   TEXT PRIMARY KEY,
+                height      INTEGER NOT NULL,
+                round       INTEGER NOT NULL,
+                proposal    TEXT NOT NULL
+            );
+            CREATE TABLE vote (
+                timestamp   TEXT PRIMARY KEY,
+                height      INTEGER NOT NULL,
+                round       INTEGER NOT NULL,
+                voter       BLOB NOT NULL,
+                vote        TEXT NOT NULL
+            );
+            CREATE TABLE cmt (
+                timestamp   TEXT PRIMARY KEY,
+                height      INTEGER NOT NULL,
+                cmt         TEXT NOT NULL
+            );
+            CREATE TABLE feed (
+                timestamp   TEXT PRIMARY KEY,
+                height      INTEGER NOT NULL,
+                feed        TEXT NOT NULL
+            );
+            CREATE TABLE status (
+                timestamp   TEXT PRIMARY KEY,
+                height      INTEGER NOT NULL,
+                status      TEXT NOT NULL
+            );",
+        )
+        .unwrap();
+
+        let rows = vec![
+            (
+                "status",
+                r#"{"height":0,"authority_list":[[0],[1],[2],[3]]}"#,
+                None,
+            ),
+            (
+                "proposal",
+                r#"{"height":1,"round":0,"content":[7],"proposer":[1],"lock_round":null,"lock_votes":[]}"#,
+                None,
+            ),
+            (
+                "vote",
+                r#"{"height":1,"round":0,"vote_type":"Prevote","proposal":[7],"voter":[1]}"#,
+                Some(vec![1u8]),
+            ),
+            (
+                "vote",
+                r#"{"height":1,"round":0,"vote_type":"Prevote","proposal":[7],"voter":[0]}"#,
+                Some(vec![0]),
+            ),
+            ("cmt", r#"{"node":0,"height":1,"result":[7]}"#, None),
+            ("feed", r#"{"height":2,"proposal":[8]}"#, None),
+        ];
+        for (i, (table, json, voter)) in rows.into_iter().enumerate() {
+            let timestamp = time::Timespec::new(100 + i as i64, 0);
+            match (table, voter) {
+                ("proposal", _) => conn.execute(
+                    "INSERT INTO proposal VALUES (?1, 1, 0, ?2)",
+                    params![timestamp, json],
+                ),
+                (_, Some(voter)) => conn.execute(
+                    "INSERT INTO vote VALUES (?1, 1, 0, ?2, ?3)",
+                    params![timestamp, voter, json],
+                ),
+                _ => conn.execute(
+                    &format!("INSERT INTO {} VALUES (?1, 1, ?2)", table),
+                    params![timestamp, json],
+                ),
+            }
+            .unwrap();
+        }
+
+        // the reader does not migrate it
+        match TraceReader::open(path) {
+            Err(FrameError::SchemaVersion(0)) => (),
+            _ => panic!("Open a database before runs were recorded"),
+        }
+        assert_eq!(migrate(&conn).unwrap(), 0);
+        assert_eq!(version(&conn).unwrap(), SCHEMA_VERSION);
+        assert!(!has_table(&conn, "vote_v0").unwrap());
+
+        // the messages are a run in the order of their timestamps, the ones of the node and
+        // the commit are received
+        let reader = TraceReader::open(path).unwrap();
+        let runs = reader.runs().unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].version, BASELINE_VERSION);
+        let records = reader.load(runs[0].run_id, &Filter::default()).unwrap();
+        let seqs: Vec<u64> = records.iter().map(|r| r.seq).collect();
+        assert_eq!(seqs, (0..6).collect::<Vec<_>>());
+        let directions: Vec<Direction> = records.iter().map(|r| r.direction).collect();
+        let (send, recv) = (Direction::Send, Direction::Recv);
+        assert_eq!(directions, vec![send, send, send, recv, recv, send]);
+        match &records[3].msg {
+            Msg::Vote(v) => assert_eq!(
+                (v.voter.clone(), v.vote_type.clone()),
+                (vec![0], VoteType::Prevote)
+            ),
+            m => panic!("Unexpected message {:?}", m),
+        }
+        remove_db(path);
+    }
+}
@@ -1,108 +1,307 @@
-use crate::whitebox::collection::util::*;
-use rusqlite::{params, Connection, Result, NO_PARAMS};
+use crate::whitebox::{
+    collection::{schema, util::*},
+    observer::Step,
+    FrameResult,
+};
+use rusqlite::{params, types::ToSql, Connection, Result};
+use serde_derive::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use time::Timespec;
 
-pub(crate) struct Storage(Connection);
+/// Direction of a message between the actuator and the node.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Sent to the node by the actuator.
+    Send,
+    /// Received from the node.
+    Recv,
+}
+
+impl Direction {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Direction::Send => "send",
+            Direction::Recv => "recv",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Direction> {
+        match name {
+            "send" => Some(Direction::Send),
+            "recv" => Some(Direction::Recv),
+            _ => None,
+        }
+    }
+}
+
+/// Where and when a message is recorded from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MsgMeta {
+    /// When the message is recorded.
+    pub timestamp: Timespec,
+    /// Whether the message is sent to or received from the node.
+    pub direction: Direction,
+    /// Step of the actuator when recorded.
+    pub step: Option<Step>,
+    /// Suite running when recorded.
+    pub suite: Option<String>,
+}
+
+/// A storage backend of the run recorded by an actuator. Messages and commit polls share a
+/// sequence number of the run, in the order they are recorded.
+pub trait Storage {
+    /// A function to get the ID of the run.
+    fn run_id(&self) -> i64;
+    /// A function to record the seed of the message delivery.
+    fn set_seed(&self, seed: u64) -> FrameResult<()>;
+    /// A function to record the seed of the random test cases.
+    fn set_case_seed(&self, seed: u64) -> FrameResult<()>;
+    /// A function to record a suite starting to run.
+    fn add_suite(&self, suite: &str) -> FrameResult<()>;
+    /// A function to record a message.
+    fn insert(&self, msg: Msg, meta: &MsgMeta) -> FrameResult<()>;
+    /// A function to record a commit poll which gets nothing from the node.
+    fn insert_poll(&self, height: u64, round: u64, meta: &MsgMeta) -> FrameResult<()>;
+    /// A function to write what is buffered, called at the end of every height and suite.
+    fn flush(&self) -> FrameResult<()> {
+        Ok(())
+    }
+}
+
+// a message or a commit poll waiting to be written
+#[derive(Clone)]
+enum Entry {
+    Msg(Msg),
+    Poll(u64, u64),
+}
+
+// entries buffered before they are written in a transaction
+const BATCH_SIZE: usize = 1024;
+
+/// A SQLite storage. Every actuator records a run, messages of all runs are kept in the same
+/// tables, which `TraceReader` reads. Messages are buffered and written in a transaction when
+/// flushed, when the buffer is full and when dropped.
+pub struct SqliteStorage {
+    conn: Connection,
+    run_id: i64,
+    seq: Cell<i64>,
+    pending: RefCell<Vec<(i64, Entry, MsgMeta)>>,
+}
+
+impl SqliteStorage {
+    /// A function to open the database, created if not exists, and start a new run.
+    pub fn new(db_path: &str) -> FrameResult<Self> {
+        let conn = Connection::open(db_path)?;
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")?;
+        schema::migrate(&conn)?;
 
-impl Storage {
-    pub(crate) fn new(db_path: &str) -> Self {
-        let conn = Connection::open(db_path).expect("Create SQLite failed!");
-        conn.execute(
-            "CREATE TABLE proposal (
-                timestamp   TEXT PRIMARY KEY,
-                height      INTEGER NOT NULL,
-                round       INTEGER NOT NULL,
-                proposal    TEXT NOT NULL
-            )",
-            NO_PARAMS,
-        )
-        .expect("Create proposal table failed!");
-        conn.execute(
-            "CREATE TABLE vote (
-                timestamp   TEXT PRIMARY KEY,
-                height      INTEGER NOT NULL,
-                round       INTEGER NOT NULL,
-                voter       BLOB NOT NULL,
-                vote        TEXT NOT NULL
-            )",
-            NO_PARAMS,
-        )
-        .expect("Create vote table failed!");
-        conn.execute(
-            "CREATE TABLE cmt (
-                timestamp   TEXT PRIMARY KEY,
-                height      INTEGER NOT NULL,
-                cmt         TEXT NOT NULL
-            )",
-            NO_PARAMS,
-        )
-        .expect("Create commit table failed!");
-        conn.execute(
-            "CREATE TABLE feed (
-                timestamp   TEXT PRIMARY KEY,
-                height      INTEGER NOT NULL,
-                feed        TEXT NOT NULL
-            )",
-            NO_PARAMS,
-        )
-        .expect("Create vote table failed!");
         conn.execute(
-            "CREATE TABLE status (
-                timestamp   TEXT PRIMARY KEY,
-                height      INTEGER NOT NULL,
-                status      TEXT NOT NULL
-            )",
-            NO_PARAMS,
-        )
-        .expect("Create vote table failed!");
+            "INSERT INTO runs (start_time, seed, suites, version) VALUES (?1, NULL, '[]', ?2)",
+            params![time::get_time(), env!("CARGO_PKG_VERSION")],
+        )?;
+        let run_id = conn.last_insert_rowid();
+        Ok(SqliteStorage {
+            conn,
+            run_id,
+            seq: Cell::new(0),
+            pending: RefCell::new(Vec::new()),
+        })
+    }
 
-        Storage(conn)
+    fn push(&self, entry: Entry, meta: &MsgMeta) -> FrameResult<()> {
+        let seq = self.seq.get();
+        let len = {
+            let mut pending = self.pending.borrow_mut();
+            pending.push((seq, entry, meta.clone()));
+            pending.len()
+        };
+        self.seq.set(seq + 1);
+        if len >= BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
     }
 
-    pub(crate) fn insert(&self, msg: Msg) -> Result<()> {
+    fn write(&self, seq: i64, entry: Entry, meta: &MsgMeta) -> Result<()> {
+        let step = meta.step.map(|s| format!("{:?}", s));
+        let head = params![
+            self.run_id,
+            seq,
+            meta.timestamp,
+            meta.direction.as_str(),
+            step,
+            meta.suite,
+        ];
+        let head = head.iter().cloned();
+        let msg = match entry {
+            Entry::Msg(msg) => msg,
+            Entry::Poll(height, round) => {
+                self.exec(
+                    "INSERT INTO poll (run_id, seq, timestamp, direction, step, suite, height, round)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    head.chain(params![height as i64, round as i64].iter().cloned()),
+                )?;
+                return Ok(());
+            }
+        };
         match msg {
             Msg::Proposal(p) => {
                 let p = StorageProposal::from_proposal(p);
-                self.0.execute(
-                    "INSERT INTO proposal (timestamp, height, round, proposal)
-                        VALUES (?1, ?2, ?3, ?4)",
-                    params![p.timestamp, p.height, p.round, p.proposal],
+                self.exec(
+                    "INSERT INTO proposal
+                        (run_id, seq, timestamp, direction, step, suite, height, round, proposal,
+                        content, proposer, lock_round)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                    head.chain(
+                        params![
+                            p.height,
+                            p.round,
+                            p.proposal,
+                            p.content,
+                            p.proposer,
+                            p.lock_round
+                        ]
+                        .iter()
+                        .cloned(),
+                    ),
                 )?;
             }
             Msg::Vote(v) => {
                 let v = StorageVote::from_vote(v);
-                self.0.execute(
-                    "INSERT INTO vote (timestamp, height, round, voter, vote)
-                        VALUES (?1, ?2, ?3, ?4, ?5)",
-                    params![v.timestamp, v.height, v.round, v.voter, v.vote],
+                self.exec(
+                    "INSERT INTO vote
+                        (run_id, seq, timestamp, direction, step, suite, height, round, voter, vote,
+                        vote_type, proposal)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                    head.chain(
+                        params![v.height, v.round, v.voter, v.vote, v.vote_type, v.proposal]
+                            .iter()
+                            .cloned(),
+                    ),
                 )?;
             }
             Msg::Commit(c) => {
                 let c = StorageCommit::from_commit(c);
-                self.0.execute(
-                    "INSERT INTO cmt (timestamp, height, cmt)
-                        VALUES (?1, ?2, ?3)",
-                    params![c.timestamp, c.height, c.commit],
+                self.exec(
+                    "INSERT INTO cmt
+                        (run_id, seq, timestamp, direction, step, suite, height, cmt, result)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    head.chain(params![c.height, c.commit, c.result].iter().cloned()),
                 )?;
             }
             Msg::Feed(f) => {
                 let f = StorageFeed::from_feed(f);
-                self.0.execute(
-                    "INSERT INTO feed (timestamp, height, feed)
-                        VALUES (?1, ?2, ?3)",
-                    params![f.timestamp, f.height, f.proposal],
+                self.exec(
+                    "INSERT INTO feed (run_id, seq, timestamp, direction, step, suite, height, feed)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    head.chain(params![f.height, f.proposal].iter().cloned()),
                 )?;
             }
             Msg::Status(s) => {
                 let s = StorageStatus::from_status(s);
-                self.0.execute(
-                    "INSERT INTO status (timestamp, height, status)
-                        VALUES (?1, ?2, ?3)",
-                    params![s.timestamp, s.height, s.authority],
+                self.exec(
+                    "INSERT INTO status
+                        (run_id, seq, timestamp, direction, step, suite, height, status)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    head.chain(params![s.height, s.authority].iter().cloned()),
                 )?;
             }
         }
         Ok(())
     }
+
+    // execute with a statement prepared once for the connection
+    fn exec<P>(&self, sql: &str, params: P) -> Result<usize>
+    where
+        P: IntoIterator,
+        P::Item: ToSql,
+    {
+        self.conn.prepare_cached(sql)?.execute(params)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn run_id(&self) -> i64 {
+        self.run_id
+    }
+
+    fn set_seed(&self, seed: u64) -> FrameResult<()> {
+        self.conn.execute(
+            "UPDATE runs SET seed = ?1 WHERE run_id = ?2",
+            params![seed as i64, self.run_id],
+        )?;
+        Ok(())
+    }
+
+    fn set_case_seed(&self, seed: u64) -> FrameResult<()> {
+        self.conn.execute(
+            "UPDATE runs SET case_seed = ?1 WHERE run_id = ?2",
+            params![seed as i64, self.run_id],
+        )?;
+        Ok(())
+    }
+
+    fn add_suite(&self, suite: &str) -> FrameResult<()> {
+        let suites: String = self.conn.query_row(
+            "SELECT suites FROM runs WHERE run_id = ?1",
+            params![self.run_id],
+            |row| row.get(0),
+        )?;
+        let mut suites: Vec<String> = serde_json::from_str(&suites).unwrap_or_default();
+        suites.push(suite.to_string());
+        self.conn.execute(
+            "UPDATE runs SET suites = ?1 WHERE run_id = ?2",
+            params![serde_json::to_string(&suites).unwrap(), self.run_id],
+        )?;
+        Ok(())
+    }
+
+    fn insert(&self, msg: Msg, meta: &MsgMeta) -> FrameResult<()> {
+        self.push(Entry::Msg(msg), meta)
+    }
+
+    fn insert_poll(&self, height: u64, round: u64, meta: &MsgMeta) -> FrameResult<()> {
+        self.push(Entry::Poll(height, round), meta)
+    }
+
+    fn flush(&self) -> FrameResult<()> {
+        if self.pending.borrow().is_empty() {
+            return Ok(());
+        }
+        // the buffer is kept until committed, so a failed batch can be flushed again
+        self.conn.execute_batch("BEGIN")?;
+        for (seq, entry, meta) in self.pending.borrow().iter() {
+            if let Err(e) = self.write(*seq, entry.clone(), meta) {
+                self.conn.execute_batch("ROLLBACK")?;
+                return Err(e.into());
+            }
+        }
+        self.conn.execute_batch("COMMIT")?;
+        self.pending.borrow_mut().clear();
+        Ok(())
+    }
+}
+
+// remove the database with the WAL files
+#[cfg(test)]
+pub(crate) fn remove_db(path: &str) {
+    for suffix in ["", "-wal", "-shm"].iter() {
+        let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+    }
+}
+
+impl Drop for SqliteStorage {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            eprintln!(
+                "Lose {} messages of run {} not flushed: {}",
+                self.pending.borrow().len(),
+                self.run_id,
+                e
+            );
+        }
+    }
 }
 
 #[cfg(test)]
@@ -119,6 +318,15 @@ mod test {
         (0..160).map(|_| random::<u8>()).collect()
     }
 
+    fn meta() -> MsgMeta {
+        MsgMeta {
+            timestamp: time::get_time(),
+            direction: Direction::Send,
+            step: Some(Step::Prevote),
+            suite: Some("test db".to_string()),
+        }
+    }
+
     fn generate_msg() -> Vec<Msg> {
         let mut msg = Vec::new();
         msg.push(Msg::Proposal(Proposal {
@@ -128,6 +336,7 @@ mod test {
             proposer: generate_addr(),
             lock_round: None,
             lock_votes: Vec::new(),
+            signature: Vec::new(),
         }));
         let prevote = Vote {
             height: 1,
@@ -135,6 +344,7 @@ mod test {
             vote_type: VoteType::Prevote,
             proposal: generate_kb(),
             voter: generate_addr(),
+            signature: Vec::new(),
         };
         msg.push(Msg::Vote(prevote.clone()));
         let precommit = Vote {
@@ -143,6 +353,7 @@ mod test {
             vote_type: VoteType::Precommit,
             proposal: generate_kb(),
             voter: generate_addr(),
+            signature: Vec::new(),
         };
         msg.push(Msg::Vote(precommit.clone()));
         msg.push(Msg::Proposal(Proposal {
@@ -152,6 +363,7 @@ mod test {
             proposer: generate_addr(),
             lock_round: Some(1),
             lock_votes: vec![prevote.clone(), prevote.clone(), prevote.clone()],
+            signature: Vec::new(),
         }));
         msg.push(Msg::Feed(Feed {
             height: 2,
@@ -161,6 +373,7 @@ mod test {
             node: 0,
             height: 2,
             result: generate_kb(),
+            proof: vec![precommit.clone()],
         }));
         msg.push(Msg::Status(Status {
             height: 3,
@@ -176,13 +389,95 @@ mod test {
 
     #[test]
     fn test_db() {
-        let conn = Storage::new("db/test.db");
+        let conn = SqliteStorage::new("db/test.db").unwrap();
+        conn.add_suite("test db").unwrap();
         let message = generate_msg();
         for msg in message.into_iter() {
-            let res = conn.insert(msg.clone());
+            let res = conn.insert(msg.clone(), &meta());
             if res.is_err() {
                 panic!("SQLite error {:?}", res);
             }
         }
+        conn.flush().unwrap();
+    }
+
+    #[test]
+    fn test_reopen() {
+        let path = std::env::temp_dir().join(format!("bft_test_{}.db", random::<u64>()));
+        let path = path.to_str().unwrap();
+        let first = SqliteStorage::new(path).unwrap();
+        first.set_seed(7).unwrap();
+        first.add_suite("a").unwrap();
+        first.add_suite("b").unwrap();
+        let second = SqliteStorage::new(path).unwrap();
+        assert_ne!(first.run_id(), second.run_id());
+        for msg in generate_msg().into_iter() {
+            first.insert(msg.clone(), &meta()).unwrap();
+            second.insert(msg, &meta()).unwrap();
+        }
+        first.flush().unwrap();
+
+        let (seed, suites): (Option<i64>, String) = second
+            .conn
+            .query_row(
+                "SELECT seed, suites FROM runs WHERE run_id = ?1",
+                params![first.run_id()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(seed, Some(7));
+        assert_eq!(suites, r#"["a","b"]"#);
+
+        // messages of a run are in a total order across the tables
+        let mut seqs = Vec::new();
+        for table in ["proposal", "vote", "cmt", "feed", "status"].iter() {
+            let mut stmt = second
+                .conn
+                .prepare(&format!("SELECT seq FROM {} WHERE run_id = ?1", table))
+                .unwrap();
+            let rows = stmt
+                .query_map(params![first.run_id()], |row| row.get::<_, i64>(0))
+                .unwrap();
+            seqs.extend(rows.map(|r| r.unwrap()));
+        }
+        seqs.sort_unstable();
+        assert_eq!(seqs, (0..generate_msg().len() as i64).collect::<Vec<_>>());
+        remove_db(path);
+    }
+
+    #[test]
+    fn test_failed_flush() {
+        let path = std::env::temp_dir().join(format!("bft_test_{}.db", random::<u64>()));
+        let path = path.to_str().unwrap();
+        let storage = SqliteStorage::new(path).unwrap();
+        let count = |storage: &SqliteStorage| -> i64 {
+            storage
+                .conn
+                .query_row("SELECT COUNT(*) FROM feed", rusqlite::NO_PARAMS, |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        };
+        for msg in generate_msg().into_iter() {
+            storage.insert(msg, &meta()).unwrap();
+        }
+
+        // the whole batch is rolled back if a table is missing, and kept to flush again
+        storage
+            .conn
+            .execute_batch("ALTER TABLE vote RENAME TO vote_away")
+            .unwrap();
+        assert!(storage.flush().is_err());
+        assert_eq!(count(&storage), 0);
+        assert_eq!(storage.pending.borrow().len(), generate_msg().len());
+
+        storage
+            .conn
+            .execute_batch("ALTER TABLE vote_away RENAME TO vote")
+            .unwrap();
+        storage.flush().unwrap();
+        assert_eq!(count(&storage), 1);
+        assert!(storage.pending.borrow().is_empty());
+        remove_db(path);
     }
 }
@@ -1,13 +1,20 @@
 use crate::whitebox::{Commit, Feed, Proposal, Status, Vote};
+use serde_derive::{Deserialize, Serialize};
 use serde_json::to_string;
 use time::Timespec;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub(crate) enum Msg {
+/// A message recorded in the storage.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Msg {
+    /// A proposal.
     Proposal(Proposal),
+    /// A prevote or precommit.
     Vote(Vote),
+    /// A commit of the node.
     Commit(Commit),
+    /// A feed to the node.
     Feed(Feed),
+    /// A status sent to the node.
     Status(Status),
 }
 
@@ -17,6 +24,9 @@ pub(crate) struct StorageProposal {
     pub(crate) height: i64,
     pub(crate) round: i64,
     pub(crate) proposal: String,
+    pub(crate) content: Vec<u8>,
+    pub(crate) proposer: Vec<u8>,
+    pub(crate) lock_round: Option<i64>,
 }
 
 impl StorageProposal {
@@ -26,6 +36,9 @@ impl StorageProposal {
             height: proposal.height as i64,
             round: proposal.round as i64,
             proposal: to_string(&proposal).unwrap(),
+            content: proposal.content,
+            proposer: proposal.proposer,
+            lock_round: proposal.lock_round.map(|r| r as i64),
         }
     }
 }
@@ -37,6 +50,8 @@ pub(crate) struct StorageVote {
     pub(crate) round: i64,
     pub(crate) voter: Vec<u8>,
     pub(crate) vote: String,
+    pub(crate) vote_type: String,
+    pub(crate) proposal: Vec<u8>,
 }
 
 impl StorageVote {
@@ -47,6 +62,8 @@ impl StorageVote {
             round: vote.round as i64,
             voter: vote.voter.clone(),
             vote: to_string(&vote).unwrap(),
+            vote_type: format!("{:?}", vote.vote_type),
+            proposal: vote.proposal,
         }
     }
 }
@@ -56,6 +73,7 @@ pub(crate) struct StorageCommit {
     pub(crate) timestamp: Timespec,
     pub(crate) height: i64,
     pub(crate) commit: String,
+    pub(crate) result: Vec<u8>,
 }
 
 impl StorageCommit {
@@ -64,6 +82,7 @@ impl StorageCommit {
             timestamp: time::get_time(),
             height: commit.height as i64,
             commit: to_string(&commit).unwrap(),
+            result: commit.result,
         }
     }
 }
@@ -72,6 +72,28 @@ impl VoteCache {
             .and_then(|rc| rc.get_voteset(round, vote_type))
     }
 
+    // all the votes of the height, one of each voter in every round and vote type
+    pub(crate) fn votes_of_height(&self, height: u64) -> Vec<Vote> {
+        let mut res = Vec::new();
+        for (_, rc) in self.votes.iter().filter(|(h, _)| **h == height) {
+            for (round, sc) in rc.round_votes.iter() {
+                for (vote_type, vs) in sc.step_votes.iter() {
+                    for (voter, proposal) in vs.votes_by_sender.iter() {
+                        res.push(Vote {
+                            height,
+                            round: *round,
+                            vote_type: vote_type.clone(),
+                            proposal: proposal.clone(),
+                            voter: voter.clone(),
+                            signature: Vec::new(),
+                        });
+                    }
+                }
+            }
+        }
+        res
+    }
+
     pub(crate) fn clear_prevote_count(&mut self) {
         self.prevote_count.clear();
     }
@@ -106,30 +128,6 @@ impl VoteSet {
         }
         is_add
     }
-
-    pub(crate) fn extract_polc(
-        &self,
-        height: u64,
-        round: u64,
-        vote_type: VoteType,
-        proposal: &[u8],
-    ) -> Vec<Vote> {
-        // abstract the votes for the polc proposal into a vec
-        let mut polc = Vec::new();
-        for (address, vote_proposal) in &self.votes_by_sender {
-            let proposal = proposal.to_vec();
-            if vote_proposal == &proposal {
-                polc.push(Vote {
-                    vote_type: vote_type.clone(),
-                    height,
-                    round,
-                    proposal: proposal.clone(),
-                    voter: address.clone(),
-                });
-            }
-        }
-        polc
-    }
 }
 
 // round -> step collector
@@ -1,4 +1,6 @@
-///
+/// Reference proposer elections.
+pub mod proposer;
+/// Random generation of the test cases.
 pub mod random;
-///
+/// Test units and suites.
 pub mod test_case;
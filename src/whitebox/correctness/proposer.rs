@@ -0,0 +1,260 @@
+use std::cell::RefCell;
+
+/// A reference proposer election algorithm.
+pub trait ProposerElection {
+    /// A function to get the index in the authority list of the proposer at the given height
+    /// and round.
+    fn proposer(&self, height: u64, round: u64) -> usize;
+}
+
+/// Round-robin proposer election, the proposer is `(height + round) % authority_num`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoundRobin {
+    authority_num: usize,
+}
+
+impl RoundRobin {
+    /// A function to create a new round-robin election.
+    pub fn new(authority_num: usize) -> Self {
+        assert!(authority_num > 0, "Empty authority list!");
+        RoundRobin { authority_num }
+    }
+}
+
+impl ProposerElection for RoundRobin {
+    fn proposer(&self, height: u64, round: u64) -> usize {
+        let n = self.authority_num as u64;
+        ((height % n + round % n) % n) as usize
+    }
+}
+
+/// Tendermint weighted priority proposer election.
+///
+/// Every increment adds each authority's weight to its priority, elects the authority with
+/// the highest priority (the lowest index wins a tie) and subtracts the total weight from the
+/// elected one. The proposer of `(height, round)` is the one elected by the
+/// `height + round + 1`th increment, so equal weights give the same answer as `RoundRobin`.
+///
+/// The priorities come back to zero after every `total` increments, so the weights are
+/// divided by their greatest common divisor first. If their total is still above
+/// `MAX_TOTAL_WEIGHT`, they are scaled down to it, rounded to the nearest and at least 1 for
+/// a non-zero weight, which keeps each share within `1 / MAX_TOTAL_WEIGHT` per authority.
+/// The proposers elected are kept, so an earlier increment is not computed again.
+#[derive(Clone, Debug)]
+pub struct WeightedPriority {
+    weights: Vec<u64>,
+    // the weights the increments use
+    scaled: Vec<u64>,
+    total: u64,
+    state: RefCell<Priorities>,
+}
+
+/// The largest total weight `WeightedPriority` elects with, larger ones are scaled down.
+pub const MAX_TOTAL_WEIGHT: u64 = 1 << 16;
+
+#[derive(Clone, Debug)]
+struct Priorities {
+    priority: Vec<i64>,
+    // the proposer elected by each increment so far
+    proposers: Vec<usize>,
+}
+
+impl Priorities {
+    fn new(authority_num: usize) -> Self {
+        Priorities {
+            priority: vec![0; authority_num],
+            proposers: Vec::new(),
+        }
+    }
+
+    fn increment(&mut self, weights: &[u64], total: u64) {
+        let mut index = 0;
+        for (i, w) in weights.iter().enumerate() {
+            self.priority[i] += *w as i64;
+            if self.priority[i] > self.priority[index] {
+                index = i;
+            }
+        }
+        // the priorities sum to zero, so each one stays within the total weight
+        self.priority[index] -= total as i64;
+        self.proposers.push(index);
+    }
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn normalize(weights: &[u64]) -> Vec<u64> {
+    let divisor = weights.iter().fold(0, |d, w| gcd(d, u128::from(*w)));
+    let weights: Vec<u128> = weights.iter().map(|w| u128::from(*w) / divisor).collect();
+    let total: u128 = weights.iter().sum();
+    if total <= u128::from(MAX_TOTAL_WEIGHT) {
+        return weights.into_iter().map(|w| w as u64).collect();
+    }
+    let cap = u128::from(MAX_TOTAL_WEIGHT);
+    let scaled: Vec<u128> = weights
+        .into_iter()
+        .map(|w| match w {
+            0 => 0,
+            w => ((w * cap + total / 2) / total).max(1),
+        })
+        .collect();
+    let divisor = scaled.iter().fold(0, |d, w| gcd(d, *w));
+    scaled.into_iter().map(|w| (w / divisor) as u64).collect()
+}
+
+impl WeightedPriority {
+    /// A function to create a new weighted priority election with the weight of each authority.
+    pub fn new(weights: Vec<u64>) -> Self {
+        assert!(
+            weights.iter().any(|w| *w > 0),
+            "Weights of authorities are all zero!"
+        );
+        let scaled = normalize(&weights);
+        let total = scaled.iter().sum();
+        let state = RefCell::new(Priorities::new(weights.len()));
+        WeightedPriority {
+            weights,
+            scaled,
+            total,
+            state,
+        }
+    }
+
+    /// A function to get the weight of each authority.
+    pub fn weights(&self) -> &[u64] {
+        &self.weights
+    }
+}
+
+impl PartialEq for WeightedPriority {
+    fn eq(&self, other: &Self) -> bool {
+        self.weights == other.weights
+    }
+}
+
+impl Eq for WeightedPriority {}
+
+impl ProposerElection for WeightedPriority {
+    fn proposer(&self, height: u64, round: u64) -> usize {
+        let target = ((u128::from(height) + u128::from(round)) % u128::from(self.total)) as usize;
+        let mut state = self.state.borrow_mut();
+        while state.proposers.len() <= target {
+            state.increment(&self.scaled, self.total);
+        }
+        state.proposers[target]
+    }
+}
+
+/// A report of the proposer distribution answered by the node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProposerReport {
+    /// Proposed times of each authority.
+    pub distribution: Vec<usize>,
+    /// Total times of proposer election.
+    pub total: usize,
+}
+
+impl ProposerReport {
+    pub(crate) fn new(authority_num: usize) -> Self {
+        ProposerReport {
+            distribution: vec![0; authority_num],
+            total: 0,
+        }
+    }
+
+    pub(crate) fn add(&mut self, index: usize) {
+        self.distribution[index] += 1;
+        self.total += 1;
+    }
+
+    /// A function to get the proportion of proposals by the authority.
+    pub fn share(&self, index: usize) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.distribution[index] as f64 / self.total as f64
+    }
+
+    /// A function to get the largest gap between the share of an authority and the expected
+    /// share from the weights.
+    pub fn max_deviation(&self, weights: &[u64]) -> f64 {
+        let total: f64 = weights.iter().map(|w| *w as f64).sum();
+        weights
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (self.share(i) - *w as f64 / total).abs())
+            .fold(0.0, f64::max)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_equal_weights() {
+        let rr = RoundRobin::new(4);
+        let wp = WeightedPriority::new(vec![1, 1, 1, 1]);
+        for h in 0..20 {
+            for r in 0..10 {
+                assert_eq!(rr.proposer(h, r), wp.proposer(h, r));
+            }
+        }
+        assert_eq!(rr.proposer(u64::MAX, u64::MAX), 2);
+    }
+
+    #[test]
+    fn test_weighted_fairness() {
+        let weights = vec![1, 2, 3, 4];
+        let wp = WeightedPriority::new(weights.clone());
+        let mut report = ProposerReport::new(4);
+        for h in 0..100 {
+            report.add(wp.proposer(h, 0));
+        }
+        assert_eq!(report.distribution, vec![10, 20, 30, 40]);
+        assert!(report.max_deviation(&weights) < 1e-9);
+    }
+
+    #[test]
+    fn test_stake_weights() {
+        // the total weight overflows u64
+        let weights = vec![u64::MAX, u64::MAX / 2, u64::MAX / 2];
+        let wp = WeightedPriority::new(weights);
+        let proposers: Vec<usize> = (0..4).map(|h| wp.proposer(h, 0)).collect();
+        assert_eq!(proposers, vec![0, 1, 2, 0]);
+        // an earlier height after a later one
+        assert_eq!(wp.proposer(1, 0), 1);
+        assert_eq!(
+            wp.proposer(u64::MAX, u64::MAX),
+            wp.proposer(u64::MAX, u64::MAX)
+        );
+
+        // stakes of a common unit elect exactly by their shares
+        let weights = vec![3_000_000_000_000, 2_000_000_000_000, 5_000_000_000_000];
+        let wp = WeightedPriority::new(weights.clone());
+        let mut report = ProposerReport::new(3);
+        for h in (0..100).rev() {
+            report.add(wp.proposer(h, 0));
+        }
+        assert_eq!(report.distribution, vec![30, 20, 50]);
+
+        // coprime stakes are scaled down to the largest total weight
+        let weights = vec![1_000_003, 999_983, 7_654_321, 12_345_678_901];
+        let wp = WeightedPriority::new(weights.clone());
+        let mut report = ProposerReport::new(4);
+        for h in (0..MAX_TOTAL_WEIGHT).rev() {
+            for r in (0..3).rev() {
+                wp.proposer(h, r);
+            }
+            report.add(wp.proposer(h, 0));
+        }
+        assert!(report.max_deviation(&weights) < 1e-3);
+        wp.proposer(u64::MAX, u64::MAX);
+    }
+}
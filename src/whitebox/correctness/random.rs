@@ -1,10 +1,31 @@
 use crate::whitebox::correctness::test_case::BftTestUnit;
-use rand::{thread_rng, Rng};
+use rand::{prng::XorShiftRng, thread_rng, Rng, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    // random test cases of the thread are generated by it, so a seed reproduces them
+    static CASE_RNG: RefCell<XorShiftRng> =
+        RefCell::new(XorShiftRng::from_rng(thread_rng()).unwrap());
+}
+
+/// A function to seed the generation of the random test cases in this thread.
+pub fn seed_cases(seed: u64) {
+    CASE_RNG.with(|rng| *rng.borrow_mut() = XorShiftRng::seed_from_u64(seed));
+}
+
+pub(crate) fn with_case_rng<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut XorShiftRng) -> R,
+{
+    CASE_RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+fn two_indexes() -> (usize, usize) {
+    with_case_rng(|rng| (rng.gen_range(0, 3), rng.gen_range(3, 6)))
+}
 
 pub(crate) fn rand_attribute(attri: u8, base: u8) -> BftTestUnit {
-    let mut rng = thread_rng();
-    let index_1: usize = rng.gen_range(0, 3);
-    let index_2: usize = rng.gen_range(3, 6);
+    let (index_1, index_2) = two_indexes();
     let mut unit = [base; 6];
     for (index, item) in unit.iter_mut().enumerate() {
         if index == index_1 || index == index_2 {
@@ -15,14 +36,39 @@ pub(crate) fn rand_attribute(attri: u8, base: u8) -> BftTestUnit {
 }
 
 pub(crate) fn rand_two_attribute(attri: u8, base: u8) -> BftTestUnit {
-    let mut rng = thread_rng();
-    let index_1: usize = rng.gen_range(0, 3);
-    let index_2: usize = rng.gen_range(3, 6);
+    let (index_1, index_2) = two_indexes();
     let mut unit = [base; 6];
     for (index, item) in unit.iter_mut().enumerate() {
-        if index != index_1 || index != index_2 {
+        if index != index_1 && index != index_2 {
             *item = attri;
         }
     }
     unit
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rand_two_attribute() {
+        for _ in 0..20 {
+            let unit = rand_two_attribute(1, 0);
+            // one prevote and one precommit are left as the base
+            assert_eq!(unit[0..3].iter().filter(|a| **a == 1).count(), 2);
+            assert_eq!(unit[3..6].iter().filter(|a| **a == 1).count(), 2);
+        }
+    }
+
+    #[test]
+    fn test_seed_cases() {
+        let cases = |seed| {
+            seed_cases(seed);
+            (0..20)
+                .map(|_| rand_attribute(0, 1))
+                .collect::<Vec<BftTestUnit>>()
+        };
+        assert_eq!(cases(7), cases(7));
+        assert_ne!(cases(7), cases(8));
+    }
+}
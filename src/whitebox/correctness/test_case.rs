@@ -1,5 +1,5 @@
 use crate::whitebox::correctness::random::*;
-use rand::random;
+use rand::Rng;
 use std::collections::HashMap;
 
 /// A basic test unit.
@@ -10,6 +10,19 @@ pub type BftTest = Vec<BftTestUnit>;
 pub(crate) const OFFLINE: u8 = 0;
 pub(crate) const NORMAL: u8 = 1;
 pub(crate) const BYZANTINE: u8 = 2;
+pub(crate) const UNKNOWN: u8 = 3;
+pub(crate) const IMPERSONATOR: u8 = 4;
+pub(crate) const DUPLICATE: u8 = 5;
+pub(crate) const PAST_HEIGHT: u8 = 10;
+pub(crate) const PAST_ROUND: u8 = 11;
+pub(crate) const FUTURE_HEIGHT: u8 = 12;
+pub(crate) const MAX_HEIGHT: u8 = 13;
+pub(crate) const BAD_SIGNATURE: u8 = 15;
+pub(crate) const WRONG_SIGNER: u8 = 16;
+pub(crate) const FUTURE_HEIGHT_GAP: u64 = 100;
+pub(crate) const FORGED_PROPOSAL: [u8; 6] = [6, 6, 6, 6, 6, 6];
+pub(crate) const STALE_PROPOSAL: [u8; 6] = [14, 14, 14, 14, 14, 14];
+pub(crate) const BAD_SIGNED_PROPOSAL: [u8; 6] = [17, 17, 17, 17, 17, 17];
 pub(crate) const NULL_ROUND: [u8; 6] = [7, 7, 7, 7, 7, 7];
 pub(crate) const SHOULD_COMMIT: [u8; 6] = [8, 8, 8, 8, 8, 8];
 pub(crate) const SHOULD_NOT_COMMIT: [u8; 6] = [9, 9, 9, 9, 9, 9];
@@ -22,7 +35,7 @@ pub(crate) fn byzantine_proposal() -> Vec<Vec<u8>> {
     ]
 }
 
-///
+/// Test cases where every authority is normal, so every height commits.
 pub fn no_byzantine_cases() -> BftTest {
     let mut cases = Vec::new();
     for _ in 0..100 {
@@ -32,7 +45,7 @@ pub fn no_byzantine_cases() -> BftTest {
     cases
 }
 
-///
+/// Test cases where one authority is offline, so every height still commits.
 pub fn one_offline_cases() -> BftTest {
     let mut cases = Vec::new();
     for _ in 0..100 {
@@ -42,7 +55,8 @@ pub fn one_offline_cases() -> BftTest {
     cases
 }
 
-///
+/// Test cases where one authority votes for a byzantine proposal, so every height still
+/// commits.
 pub fn one_byzantine_cases() -> BftTest {
     let mut cases = Vec::new();
     for _ in 0..100 {
@@ -52,7 +66,8 @@ pub fn one_byzantine_cases() -> BftTest {
     cases
 }
 
-///
+/// Test cases where two authorities vote for byzantine proposals, so no height commits until
+/// they are normal again.
 pub fn two_byzantine_cases() -> BftTest {
     let mut cases = Vec::new();
     for _ in 0..99 {
@@ -64,7 +79,7 @@ pub fn two_byzantine_cases() -> BftTest {
     cases
 }
 
-///
+/// Test cases where two authorities are offline, so no height commits until they are back.
 pub fn two_offline_cases() -> BftTest {
     let mut cases = Vec::new();
     for _ in 0..10 {
@@ -78,7 +93,8 @@ pub fn two_offline_cases() -> BftTest {
     cases
 }
 
-///
+/// Test cases where two authorities are byzantine and one is offline, so no height commits
+/// until they are normal again.
 pub fn two_byzantine_one_offline() -> BftTest {
     let mut cases = Vec::new();
     for _ in 0..10 {
@@ -90,11 +106,11 @@ pub fn two_byzantine_one_offline() -> BftTest {
     cases
 }
 
-///
+/// Test cases of a random number of rounds without a commit before every commit.
 pub fn round_leap() -> BftTest {
     let mut cases = Vec::new();
     for _ in 0..10 {
-        for _ in 0..random::<u8>() {
+        for _ in 0..with_case_rng(|rng| rng.gen::<u8>()) {
             cases.push(rand_two_attribute(OFFLINE, NORMAL));
             cases.push(SHOULD_NOT_COMMIT);
         }
@@ -106,11 +122,11 @@ pub fn round_leap() -> BftTest {
     cases
 }
 
-///
+/// Test cases where the node locks on a proposal without a commit, and commits it later.
 pub fn lock_proposal() -> BftTest {
     let mut cases = Vec::new();
     for _ in 0..10 {
-        if random::<bool>() {
+        if with_case_rng(|rng| rng.gen::<bool>()) {
             cases.push([1, 1, 1, 1, 0, 2]);
         } else {
             cases.push([1, 2, 0, 1, 2, 0]);
@@ -122,7 +138,7 @@ pub fn lock_proposal() -> BftTest {
     cases
 }
 
-///
+/// Test cases where the lock of the node is kept or changed over rounds without a commit.
 pub fn proposal_with_lock() -> BftTest {
     let mut cases = Vec::new();
     for _ in 0..10 {
@@ -140,6 +156,68 @@ pub fn proposal_with_lock() -> BftTest {
     cases
 }
 
+/// Test cases of proposals and votes from authorities which are unknown, impersonated or
+/// duplicated.
+pub fn non_authority_cases() -> BftTest {
+    let mut cases = Vec::new();
+    for _ in 0..10 {
+        cases.push(FORGED_PROPOSAL);
+        cases.push([1, 1, 1, 1, 1, 1]);
+        cases.push(SHOULD_COMMIT);
+        cases.push([1, UNKNOWN, IMPERSONATOR, 1, UNKNOWN, IMPERSONATOR]);
+        cases.push(SHOULD_NOT_COMMIT);
+        cases.push([1, DUPLICATE, UNKNOWN, 1, DUPLICATE, UNKNOWN]);
+        cases.push(SHOULD_NOT_COMMIT);
+        cases.push([1, 1, 1, 1, 1, 1]);
+        cases.push(SHOULD_COMMIT);
+    }
+    cases
+}
+
+/// Test cases of proposals and votes of past or future heights and rounds, which the node
+/// should not count.
+pub fn stale_message_cases() -> BftTest {
+    let mut cases = Vec::new();
+    for _ in 0..10 {
+        cases.push(STALE_PROPOSAL);
+        cases.push([1, 1, 1, 1, 1, 1]);
+        cases.push(SHOULD_COMMIT);
+        cases.push([1, PAST_HEIGHT, FUTURE_HEIGHT, 1, PAST_ROUND, MAX_HEIGHT]);
+        cases.push(SHOULD_NOT_COMMIT);
+        cases.push([1, 1, PAST_ROUND, 1, 1, MAX_HEIGHT]);
+        cases.push(SHOULD_COMMIT);
+        cases.push([PAST_ROUND, PAST_HEIGHT, 1, PAST_ROUND, PAST_HEIGHT, 1]);
+        cases.push(SHOULD_NOT_COMMIT);
+        cases.push(STALE_PROPOSAL);
+        cases.push([1, 1, 1, 1, 1, 1]);
+        cases.push(SHOULD_COMMIT);
+    }
+    cases
+}
+
+/// Test cases of badly signed or wrongly attributed messages, which only make sense when the
+/// actuator is set with a cryptography.
+pub fn signature_cases() -> BftTest {
+    let mut cases = Vec::new();
+    for _ in 0..10 {
+        cases.push(BAD_SIGNED_PROPOSAL);
+        cases.push([1, 1, 1, 1, 1, 1]);
+        cases.push(SHOULD_COMMIT);
+        cases.push([
+            1,
+            BAD_SIGNATURE,
+            WRONG_SIGNER,
+            1,
+            BAD_SIGNATURE,
+            WRONG_SIGNER,
+        ]);
+        cases.push(SHOULD_NOT_COMMIT);
+        cases.push([1, 1, BAD_SIGNATURE, 1, 1, WRONG_SIGNER]);
+        cases.push(SHOULD_COMMIT);
+    }
+    cases
+}
+
 pub(crate) fn all_cases() -> HashMap<String, BftTest> {
     let mut test_cases = HashMap::new();
     test_cases
@@ -170,6 +248,12 @@ pub(crate) fn all_cases() -> HashMap<String, BftTest> {
         .entry("test lock proposal".to_string())
         .or_insert_with(proposal_with_lock);
     test_cases
+        .entry("test non-authority case".to_string())
+        .or_insert_with(non_authority_cases);
+    test_cases
+        .entry("test stale message case".to_string())
+        .or_insert_with(stale_message_cases);
+    test_cases
 }
 
 #[cfg(test)]
@@ -213,7 +297,14 @@ mod test {
 
     #[test]
     fn test_cases_retional() {
-        let all_test_cases = all_cases();
+        let mut all_test_cases = all_cases();
+        all_test_cases.insert("test signature case".to_string(), signature_cases());
+        let injections = [
+            NULL_ROUND,
+            FORGED_PROPOSAL,
+            STALE_PROPOSAL,
+            BAD_SIGNED_PROPOSAL,
+        ];
         let mut commit_flag: bool = true;
         for (test_name, test_case) in all_test_cases.into_iter() {
             println!("Test retional of {:?}", test_name);
@@ -224,7 +315,7 @@ mod test {
                     } else {
                         assert_eq!(case.to_vec(), SHOULD_NOT_COMMIT);
                     }
-                } else if case != &NULL_ROUND {
+                } else if !injections.contains(case) {
                     let prevote = case[0..3].to_vec();
                     let precommit = case[3..6].to_vec();
                     commit_flag = should_commit(prevote, precommit);
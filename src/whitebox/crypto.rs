@@ -0,0 +1,117 @@
+use crate::whitebox::{Address, Hash, Proposal, Vote};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+
+/// A cryptography to sign and verify the proposals and votes.
+///
+/// A signature carries whatever the implementation needs to verify it against the address of
+/// the signer, such as the public key.
+pub trait Crypto {
+    /// A function to generate a new key pair, return the private key and the public key.
+    fn gen_keypair(&self) -> (Vec<u8>, Vec<u8>);
+    /// A function to derive the public key from the private key, return `None` if the private
+    /// key is invalid.
+    fn pubkey(&self, privkey: &[u8]) -> Option<Vec<u8>>;
+    /// A function to sign the hash with the private key.
+    fn sign(&self, privkey: &[u8], hash: &[u8]) -> Vec<u8>;
+    /// A function to verify that the signature of the hash is signed by the address.
+    fn verify(&self, hash: &[u8], signature: &[u8], address: &[u8]) -> bool;
+    /// A function to derive the address from the public key.
+    fn address(&self, pubkey: &[u8]) -> Address;
+}
+
+/// A function to get the hash of a vote to sign, the signature is not included.
+pub fn vote_hash(vote: &Vote) -> Hash {
+    let mut vote = vote.clone();
+    vote.signature = Vec::new();
+    sha256(&serde_json::to_vec(&vote).unwrap())
+}
+
+/// A function to get the hash of a proposal to sign, the signature is not included.
+pub fn proposal_hash(proposal: &Proposal) -> Hash {
+    let mut proposal = proposal.clone();
+    proposal.signature = Vec::new();
+    sha256(&serde_json::to_vec(&proposal).unwrap())
+}
+
+fn sha256(msg: &[u8]) -> Hash {
+    Sha256::digest(msg).to_vec()
+}
+
+/// Ed25519 cryptography. The signature is the public key followed by the Ed25519 signature, and
+/// the address is the last 20 bytes of the SHA-256 of the public key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Ed25519;
+
+impl Ed25519 {
+    fn keypair(privkey: &[u8]) -> Option<Keypair> {
+        let secret = SecretKey::from_bytes(privkey).ok()?;
+        let public = PublicKey::from(&secret);
+        Some(Keypair { secret, public })
+    }
+}
+
+impl Crypto for Ed25519 {
+    fn gen_keypair(&self) -> (Vec<u8>, Vec<u8>) {
+        let mut rng = thread_rng();
+        let privkey: Vec<u8> = (0..32).map(|_| rng.gen()).collect();
+        let pubkey = self.pubkey(&privkey).unwrap();
+        (privkey, pubkey)
+    }
+
+    fn pubkey(&self, privkey: &[u8]) -> Option<Vec<u8>> {
+        Ed25519::keypair(privkey).map(|k| k.public.to_bytes().to_vec())
+    }
+
+    fn sign(&self, privkey: &[u8], hash: &[u8]) -> Vec<u8> {
+        let keypair = Ed25519::keypair(privkey).expect("Invalid Ed25519 private key!");
+        let mut res = keypair.public.to_bytes().to_vec();
+        res.extend_from_slice(&keypair.sign(hash).to_bytes());
+        res
+    }
+
+    fn verify(&self, hash: &[u8], signature: &[u8], address: &[u8]) -> bool {
+        if signature.len() != 96 || self.address(&signature[0..32]) != address {
+            return false;
+        }
+        match (
+            PublicKey::from_bytes(&signature[0..32]),
+            Signature::try_from(&signature[32..96]),
+        ) {
+            (Ok(pubkey), Ok(sig)) => pubkey.verify(hash, &sig).is_ok(),
+            _ => false,
+        }
+    }
+
+    fn address(&self, pubkey: &[u8]) -> Address {
+        sha256(pubkey)[12..32].to_vec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ed25519() {
+        let crypto = Ed25519;
+        let (privkey, pubkey) = crypto.gen_keypair();
+        let address = crypto.address(&pubkey);
+        let hash = sha256(b"bft test");
+
+        let mut signature = crypto.sign(&privkey, &hash);
+        assert!(crypto.verify(&hash, &signature, &address));
+        assert!(!crypto.verify(&sha256(b"bft"), &signature, &address));
+
+        let (_, other) = crypto.gen_keypair();
+        assert!(!crypto.verify(&hash, &signature, &crypto.address(&other)));
+
+        signature[95] ^= 1;
+        assert!(!crypto.verify(&hash, &signature, &address));
+
+        assert_eq!(crypto.pubkey(&privkey), Some(pubkey));
+        assert_eq!(crypto.pubkey(&[1, 2, 3]), None);
+    }
+}
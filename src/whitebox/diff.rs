@@ -0,0 +1,274 @@
+use crate::whitebox::{
+    collection::{
+        query::{Filter, Record, TraceReader},
+        storage::Direction,
+        util::Msg,
+    },
+    correctness::test_case::byzantine_proposal,
+    observer::Step,
+    *,
+};
+
+use std::collections::HashMap;
+
+/// The messages of a run, recorded in the storage or by a `MemoryStorage` of a running
+/// actuator.
+pub struct History {
+    records: Vec<Record>,
+    seed: Option<u64>,
+    case_seed: Option<u64>,
+}
+
+impl History {
+    /// A function to create a history of the records in the recorded order, with the seed of
+    /// the message delivery and the seed of the random test cases if set.
+    pub fn new(records: Vec<Record>, seed: Option<u64>, case_seed: Option<u64>) -> Self {
+        History {
+            records,
+            seed,
+            case_seed,
+        }
+    }
+
+    /// A function to load the history of the run in the storage.
+    pub fn load(reader: &TraceReader, run_id: i64) -> FrameResult<Self> {
+        let (seed, case_seed) = reader
+            .runs()?
+            .into_iter()
+            .find(|r| r.run_id == run_id)
+            .map_or((None, None), |r| (r.seed, r.case_seed));
+        Ok(History {
+            records: reader.load(run_id, &Filter::default())?,
+            seed,
+            case_seed,
+        })
+    }
+}
+
+/// A difference of the messages the node sends in a height, round and step between two runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Difference {
+    /// Height of the messages.
+    pub height: u64,
+    /// `None` for the commits.
+    pub round: Option<u64>,
+    /// `Propose` for the proposals, and `Commit` for the commits.
+    pub step: Step,
+    /// What the node sends in the left run, in the recorded order.
+    pub left: Vec<Msg>,
+    /// What the node sends in the right run, in the recorded order.
+    pub right: Vec<Msg>,
+}
+
+type Key = (u64, Option<u64>, Step);
+
+/// A function to compare the proposals, votes and commits the node sends in the two runs,
+/// aligned by height, round and step. Return every difference in the order of height, round
+/// and step, so the first one is where the runs diverge.
+///
+/// The proposal contents are generated by the delivery seed, so if the runs are not of the
+/// same delivery seed and case seed, the hashes are compared by the order they first appear in
+/// the run. Signatures are never compared.
+pub fn diff(left: &History, right: &History) -> Vec<Difference> {
+    let strict =
+        left.seed.is_some() && left.seed == right.seed && left.case_seed == right.case_seed;
+    let left = node_msgs(left, strict);
+    let right = node_msgs(right, strict);
+
+    let mut keys: Vec<Key> = left.keys().chain(right.keys()).cloned().collect();
+    keys.sort_by_key(|(height, round, step)| (*height, round.is_none(), *round, step_order(*step)));
+    keys.dedup();
+
+    let mut res = Vec::new();
+    for key in keys.into_iter() {
+        let (l, r) = (left.get(&key), right.get(&key));
+        let normalized = |msgs: Option<&Vec<(Msg, Msg)>>| {
+            msgs.map(|m| m.iter().map(|(n, _)| n.clone()).collect::<Vec<_>>())
+                .unwrap_or_default()
+        };
+        if normalized(l) != normalized(r) {
+            let origin = |msgs: Option<&Vec<(Msg, Msg)>>| {
+                msgs.map(|m| m.iter().map(|(_, o)| o.clone()).collect())
+                    .unwrap_or_default()
+            };
+            let (height, round, step) = key;
+            res.push(Difference {
+                height,
+                round,
+                step,
+                left: origin(l),
+                right: origin(r),
+            });
+        }
+    }
+    res
+}
+
+fn step_order(step: Step) -> u8 {
+    match step {
+        Step::Propose => 0,
+        Step::Prevote => 1,
+        Step::Precommit => 2,
+        Step::Commit => 3,
+    }
+}
+
+// the normalized and the original messages the node sends by height, round and step
+fn node_msgs(history: &History, strict: bool) -> HashMap<Key, Vec<(Msg, Msg)>> {
+    let mut names = Names::new();
+    let mut res: HashMap<Key, Vec<(Msg, Msg)>> = HashMap::new();
+    for record in history.records.iter() {
+        // name the hashes in the order they appear, whoever sends them
+        let normalized = if strict {
+            record.msg.clone()
+        } else {
+            names.normalize(&record.msg)
+        };
+        if record.direction != Direction::Recv {
+            continue;
+        }
+        let key = match &record.msg {
+            Msg::Proposal(p) => (p.height, Some(p.round), Step::Propose),
+            Msg::Vote(v) if v.vote_type == VoteType::Prevote => {
+                (v.height, Some(v.round), Step::Prevote)
+            }
+            Msg::Vote(v) => (v.height, Some(v.round), Step::Precommit),
+            Msg::Commit(c) => (c.height, None, Step::Commit),
+            _ => continue,
+        };
+        res.entry(key)
+            .or_default()
+            .push((normalized, record.msg.clone()));
+    }
+    res
+}
+
+// names of the hashes in a run, the nil and byzantine proposals are kept as they are fixed
+struct Names {
+    names: HashMap<Hash, Hash>,
+    fixed: Vec<Hash>,
+}
+
+impl Names {
+    fn new() -> Self {
+        Names {
+            names: HashMap::new(),
+            fixed: byzantine_proposal(),
+        }
+    }
+
+    fn name(&mut self, hash: &[u8]) -> Hash {
+        if hash.is_empty() || self.fixed.iter().any(|f| f.as_slice() == hash) {
+            return hash.to_vec();
+        }
+        let next = self.names.len() as u64;
+        self.names
+            .entry(hash.to_vec())
+            .or_insert_with(|| format!("#{}", next).into_bytes())
+            .clone()
+    }
+
+    fn vote(&mut self, vote: &Vote) -> Vote {
+        Vote {
+            proposal: self.name(&vote.proposal),
+            signature: Vec::new(),
+            ..vote.clone()
+        }
+    }
+
+    fn normalize(&mut self, msg: &Msg) -> Msg {
+        match msg {
+            Msg::Proposal(p) => Msg::Proposal(Proposal {
+                content: self.name(&p.content),
+                lock_votes: p.lock_votes.iter().map(|v| self.vote(v)).collect(),
+                signature: Vec::new(),
+                ..p.clone()
+            }),
+            Msg::Vote(v) => Msg::Vote(self.vote(v)),
+            Msg::Commit(c) => Msg::Commit(Commit {
+                result: self.name(&c.result),
+                proof: c.proof.iter().map(|v| self.vote(v)).collect(),
+                ..c.clone()
+            }),
+            Msg::Feed(f) => Msg::Feed(Feed {
+                proposal: self.name(&f.proposal),
+                ..f.clone()
+            }),
+            Msg::Status(s) => Msg::Status(s.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testkit::{Mutation, ReferenceNode};
+    use crate::whitebox::{
+        actuator::Actuator,
+        collection::{backend::MemoryStorage, storage::remove_db},
+        correctness::test_case::{BftTest, SHOULD_COMMIT, SHOULD_NOT_COMMIT},
+        scheduler::Delivery,
+    };
+    use rand::random;
+
+    fn cases() -> BftTest {
+        vec![
+            [1, 2, 0, 1, 2, 0],
+            SHOULD_NOT_COMMIT,
+            [1, 1, 1, 1, 1, 1],
+            SHOULD_COMMIT,
+        ]
+    }
+
+    fn history(mutation: Option<Mutation>, seed: Option<u64>) -> History {
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let node = match mutation {
+            Some(m) => ReferenceNode::with_mutation(authority_list.clone(), m),
+            None => ReferenceNode::new(authority_list.clone()),
+        };
+        let storage = MemoryStorage::new();
+        let mut actuator =
+            Actuator::with_storage(node, 0, 0, authority_list, Box::new(storage.clone())).unwrap();
+        if let Some(seed) = seed {
+            actuator.set_delivery(Delivery::new(seed)).unwrap();
+        }
+        let _ = actuator.proc_test(cases());
+        History::new(storage.records(), storage.seed(), storage.case_seed())
+    }
+
+    #[test]
+    fn test_diff() {
+        // a stored run against a live one, of random proposal contents
+        let path = std::env::temp_dir().join(format!("bft_diff_{}.db", random::<u64>()));
+        let path = path.to_str().unwrap();
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let node = ReferenceNode::new(authority_list.clone());
+        let mut actuator = Actuator::new(node, 0, 0, authority_list, path).unwrap();
+        actuator.proc_test(cases()).unwrap();
+        let reader = TraceReader::open(path).unwrap();
+        let stored = History::load(&reader, actuator.run_id()).unwrap();
+        let live = history(None, None);
+        assert!(diff(&stored, &live).is_empty());
+
+        // the contents are compared as they are in the runs of the same seed
+        let seeded = history(None, Some(1));
+        assert!(seeded.records.iter().any(|r| match &r.msg {
+            Msg::Proposal(p) => !p.content.is_empty(),
+            _ => false,
+        }));
+        assert!(diff(&seeded, &history(None, Some(1))).is_empty());
+
+        // the node precommits the proposal instead of nil without a prevote quorum
+        let mutated = history(Some(Mutation::PrecommitWithoutQuorum), None);
+        let differences = diff(&live, &mutated);
+        assert!(!differences.is_empty());
+        assert_eq!(differences[0].step, Step::Precommit);
+        let precommit = |msgs: &[Msg]| match msgs {
+            [Msg::Vote(v)] => v.proposal.clone(),
+            _ => panic!("not a precommit: {:?}", msgs),
+        };
+        assert!(precommit(&differences[0].left).is_empty());
+        assert!(!precommit(&differences[0].right).is_empty());
+        remove_db(path);
+    }
+}
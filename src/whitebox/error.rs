@@ -1,31 +1,119 @@
-use crate::whitebox::*;
-use std::fmt;
+use crate::whitebox::{
+    collection::schema::SCHEMA_VERSION, correctness::test_case::BftTestUnit, invariant::Event,
+    observer::Step, *,
+};
+use std::{error::Error, fmt, io};
 
-///
+/// An error of the node under test found by the actuator, or of the test framework.
 #[derive(Debug)]
 pub enum BftError {
-    ///
+    /// A commit of another result at a committed height.
     CommitDiff(u64),
-    ///
+    /// A commit of a result without +2/3 precommits for it, or of a byzantine proposal.
     CommitIncorrect(u64),
-    ///
+    /// A commit where the test case expects no commit.
     CommitInvalid(u64),
-    ///
+    /// A commit of another height than the current one.
     MislaidCommit(u64),
-    ///
+    /// A second commit of the same result at a height.
     MultipleCommit(u64),
-    ///
+    /// A precommit for a proposal without +2/3 prevotes for it in the round.
     ShouldNotPrecommit(u64, u64),
-    ///
-    AbnormalProposal(Proposal),
-    ///
+    /// A proposal from the node when a vote is expected. The proposal is boxed since it carries
+    /// a signature, so match it as `AbnormalProposal(p)` and use `*p` for the proposal.
+    AbnormalProposal(Box<Proposal>),
+    /// A vote of the wrong type, or for a proposal the node should not vote for.
     IllegalVote(Vote),
-    ///
+    /// A precommit for another proposal than the one with +2/3 prevotes in the round.
     PrecommitErr(u64, u64),
-    ///
-    PrecommitDiffPoLC(Vote),
-    ///
+    /// A proposal of the wrong height, round or content, or no proposal when the node is the
+    /// proposer.
     IllegalProposal(u64, u64),
+    /// A proposer elected by the node different from the reference election.
+    ProposerDiff(u64, u64),
+    /// A vote or proposal of another height or round counted by the node.
+    StaleMsgCounted(u64, u64),
+    /// A vote of another height or round than the current one.
+    WrongHeightRound(Vote),
+    /// A proposal or vote of the node with an invalid signature.
+    IllegalSignature(u64, u64),
+    /// A commit proof which is not +2/3 valid precommits of the result in a round.
+    IllegalCommitProof(u64),
+    /// A violation of a user-defined invariant.
+    Violation(String),
+    /// An error with where and how the test case fails. Every error returned by
+    /// `Actuator::proc_test` and `Actuator::all_test` is wrapped in it, so match on `root()`
+    /// for the error itself.
+    WithContext(Box<BftError>, Box<ErrorContext>),
+    /// An error of the test framework rather than the node.
+    Frame(FrameError),
+}
+
+impl BftError {
+    /// A function to get the error without the context.
+    pub fn root(&self) -> &BftError {
+        match self {
+            BftError::WithContext(e, _) => e.root(),
+            e => e,
+        }
+    }
+
+    /// A function to get the context of the error if any.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            BftError::WithContext(_, ctx) => Some(ctx),
+            _ => None,
+        }
+    }
+}
+
+/// Where and how a test case fails.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// Name of the suite, `None` if the cases are not from a named suite.
+    pub suite: Option<String>,
+    /// Index of the failed test unit in the cases.
+    pub index: usize,
+    /// The failed test unit.
+    pub unit: Option<BftTestUnit>,
+    /// Step of the actuator when failed.
+    pub step: Option<Step>,
+    /// Height of the actuator when failed.
+    pub height: u64,
+    /// Round of the actuator when failed.
+    pub round: u64,
+    /// The proposal the node is expected to propose or vote for, the lock proposal if locked.
+    pub expected: Vec<u8>,
+    /// The last message received from the node.
+    pub actual: Option<Event>,
+    /// Lock round and proposal of the actuator.
+    pub lock: Option<(u64, Vec<u8>)>,
+    /// Seed of the message delivery if set.
+    pub seed: Option<u64>,
+    /// Seed of the random test cases of `all_test` if set or generated, set it again by
+    /// `Actuator::set_case_seed` to reproduce the cases.
+    pub case_seed: Option<u64>,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "suite: {}, unit {} {:?}",
+            self.suite.as_deref().unwrap_or("-"),
+            self.index,
+            self.unit
+        )?;
+        writeln!(
+            f,
+            "step: {:?} at Height {:?}, Round {:?}",
+            self.step, self.height, self.round
+        )?;
+        writeln!(f, "expected: {:?}", self.expected)?;
+        writeln!(f, "actual: {:?}", self.actual)?;
+        writeln!(f, "lock: {:?}", self.lock)?;
+        write!(f, "seed: {:?}, case seed: {:?}", self.seed, self.case_seed)
+    }
 }
 
 impl fmt::Display for BftError {
@@ -47,27 +135,201 @@ impl fmt::Display for BftError {
             BftError::PrecommitErr(h, r) => {
                 format!("Precommit Error at Height {:?}, Round {:?}", h, r)
             }
-            BftError::PrecommitDiffPoLC(v) => format!("Precommit Different From PoLC {:?}", v),
             BftError::IllegalProposal(h, r) => {
                 format!("Illegal Proposal at Height {:?}, Round {:?}", h, r)
             }
+            BftError::ProposerDiff(h, r) => format!(
+                "Proposer Different from Reference at Height {:?}, Round {:?}",
+                h, r
+            ),
+            BftError::StaleMsgCounted(h, r) => {
+                format!("Stale Message Counted at Height {:?}, Round {:?}", h, r)
+            }
+            BftError::WrongHeightRound(v) => format!("Vote at Wrong Height or Round {:?}", v),
+            BftError::IllegalSignature(h, r) => {
+                format!("Illegal Signature at Height {:?}, Round {:?}", h, r)
+            }
+            BftError::IllegalCommitProof(h) => format!("Illegal Commit Proof at Height {:?}", h),
+            BftError::Violation(msg) => format!("Invariant Violated: {}", msg),
+            BftError::WithContext(e, ctx) => return write!(f, "{}\n{}", e, ctx),
+            BftError::Frame(e) => return write!(f, "{}", e),
         };
         f.write_fmt(format_args!("BFT Error ({})!", msg))
     }
 }
 
-///
+impl Error for BftError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BftError::WithContext(e, _) => Some(e.as_ref()),
+            BftError::Frame(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<FrameError> for BftError {
+    fn from(e: FrameError) -> Self {
+        BftError::Frame(e)
+    }
+}
+
+/// An error of the test framework, such as of the storage or an invalid test case.
 #[derive(Debug)]
 pub enum FrameError {
-    ///
-    SQLiteErr(usize),
+    /// An error of the SQLite storage.
+    Storage(rusqlite::Error),
+    /// A test unit which is not a valid scenario.
+    InvalidScenario(BftTestUnit),
+    /// An authority index beyond the authority list.
+    InvalidAuthority(usize),
+    /// An authority list of the given length, shorter than the node and the three authorities
+    /// the actuator simulates.
+    TooFewAuthorities(usize),
+    /// A private key which is invalid or not of the authority at the index.
+    InvalidKey(usize),
+    /// A probability of the message delivery out of `[0, 1]`.
+    InvalidDelivery(f64),
+    /// An I/O error.
+    Io(io::Error),
+    /// A recorded message which can not be decoded.
+    Decode(serde_json::Error),
+    /// A database of another schema version than the framework's. An older one is migrated by
+    /// `schema::migrate` or when recorded in again.
+    SchemaVersion(u32),
 }
 
 impl fmt::Display for FrameError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let msg: String = match *self {
-            FrameError::SQLiteErr(i) => format!("SQLite Error {:?}", i),
+        let msg: String = match self {
+            FrameError::Storage(e) => format!("Storage Error {}", e),
+            FrameError::InvalidScenario(unit) => format!("Invalid Test Unit {:?}", unit),
+            FrameError::InvalidAuthority(i) => {
+                format!("Authority Index {:?} beyond Authority List", i)
+            }
+            FrameError::TooFewAuthorities(n) => {
+                format!("Authority List of {} Authorities, at least 4 needed", n)
+            }
+            FrameError::InvalidKey(i) => format!("Invalid Private Key of Authority {:?}", i),
+            FrameError::InvalidDelivery(p) => format!("Delivery Probability {} out of [0, 1]", p),
+            FrameError::Io(e) => format!("I/O Error {}", e),
+            FrameError::Decode(e) => format!("Decode Error {}", e),
+            FrameError::SchemaVersion(v) if *v < SCHEMA_VERSION => format!(
+                "Schema Version {} older than {}, migrate it first",
+                v, SCHEMA_VERSION
+            ),
+            FrameError::SchemaVersion(v) => format!("Unsupported Schema Version {}", v),
         };
-        f.write_fmt(format_args!("Frame Error ({})I", msg))
+        f.write_fmt(format_args!("Frame Error ({})!", msg))
+    }
+}
+
+impl Error for FrameError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FrameError::Storage(e) => Some(e),
+            FrameError::Io(e) => Some(e),
+            FrameError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for FrameError {
+    fn from(e: rusqlite::Error) -> Self {
+        FrameError::Storage(e)
+    }
+}
+
+impl From<serde_json::Error> for FrameError {
+    fn from(e: serde_json::Error) -> Self {
+        FrameError::Decode(e)
+    }
+}
+
+impl From<io::Error> for FrameError {
+    fn from(e: io::Error) -> Self {
+        FrameError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testkit::{Mutation, ReferenceNode};
+    use crate::whitebox::{
+        actuator::Actuator, collection::backend::MemoryStorage,
+        correctness::test_case::no_byzantine_cases,
+    };
+
+    #[test]
+    fn test_error_context() {
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let node = ReferenceNode::with_mutation(authority_list.clone(), Mutation::DoubleVote);
+        let mut actuator = Actuator::new(node, 0, 0, authority_list, ":memory:").unwrap();
+        let err = actuator.proc_test(no_byzantine_cases()).unwrap_err();
+
+        let ctx = err.context().unwrap();
+        assert_eq!((ctx.suite.as_deref(), ctx.index), (None, 0));
+        assert_eq!(ctx.step, Some(Step::Precommit));
+        assert_eq!((ctx.height, ctx.round), (0, 0));
+        assert_eq!(ctx.case_seed, None);
+        // the second prevote comes when a precommit is expected
+        match ctx.actual.as_ref() {
+            Some(Event::Recv(FrameRecv::Vote(v))) => assert_eq!(v.vote_type, VoteType::Prevote),
+            e => panic!("Unexpected message {:?}", e),
+        }
+        assert!(matches!(err.root(), BftError::IllegalVote(_)));
+        assert!(matches!(
+            err.source().and_then(|e| e.downcast_ref::<BftError>()),
+            Some(BftError::IllegalVote(_))
+        ));
+    }
+
+    #[test]
+    fn test_case_seed() {
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let node = ReferenceNode::with_mutation(authority_list.clone(), Mutation::DoubleVote);
+        let storage = MemoryStorage::new();
+        let mut actuator =
+            Actuator::with_storage(node, 0, 0, authority_list, Box::new(storage.clone())).unwrap();
+        actuator.set_case_seed(5);
+        let err = actuator.all_test().unwrap_err();
+        let ctx = err.context().unwrap();
+        assert!(ctx.suite.is_some());
+        assert_eq!(ctx.case_seed, Some(5));
+        assert_eq!(storage.case_seed(), Some(5));
+    }
+
+    #[test]
+    fn test_frame_error() {
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let node = ReferenceNode::new(authority_list.clone());
+        match Actuator::new(node, 0, 0, authority_list.clone(), "db/no/such/dir.db") {
+            Err(FrameError::Storage(_)) => (),
+            _ => panic!("Open storage in a missing directory"),
+        }
+
+        let node = ReferenceNode::new(authority_list.clone());
+        match Actuator::new(node, 0, 0, authority_list[..3].to_vec(), ":memory:") {
+            Err(FrameError::TooFewAuthorities(3)) => (),
+            _ => panic!("Create an actuator of three authorities"),
+        }
+
+        let node = ReferenceNode::new(authority_list.clone());
+        let mut actuator = Actuator::new(node, 0, 0, authority_list, ":memory:").unwrap();
+        match actuator.set_authority_list(vec![vec![0]]) {
+            Err(e @ FrameError::TooFewAuthorities(1)) => assert_eq!(
+                e.to_string(),
+                "Frame Error (Authority List of 1 Authorities, at least 4 needed)!"
+            ),
+            res => panic!("Unexpected result {:?}", res),
+        }
+        let unit = [1, 1, 42, 1, 1, 1];
+        let err = actuator.proc_test(vec![unit]).unwrap_err();
+        match err.root() {
+            BftError::Frame(FrameError::InvalidScenario(u)) => assert_eq!(*u, unit),
+            e => panic!("Unexpected error {:?}", e),
+        }
     }
 }
@@ -0,0 +1,427 @@
+use crate::whitebox::{
+    crypto::{vote_hash, Crypto},
+    error::BftError,
+    observer::Step,
+    *,
+};
+
+use std::collections::HashMap;
+
+/// A message exchanged with the node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A message delivered to the node.
+    Send(FrameSend),
+    /// A message received from the node.
+    Recv(FrameRecv),
+    /// A commit received from the node.
+    Commit(Commit),
+}
+
+/// The message history and the state of the actuator when an invariant is checked.
+pub struct Context<'a> {
+    /// Current height.
+    pub height: u64,
+    /// Current round.
+    pub round: u64,
+    /// The step just answered by the node.
+    pub step: Step,
+    /// Authority list, the node is the first one.
+    pub authority_list: &'a [Vec<u8>],
+    /// Lock round of the actuator.
+    pub lock_round: Option<u64>,
+    /// Lock proposal of the actuator.
+    pub lock_proposal: Option<&'a [u8]>,
+    /// Proposal of the round, the feed if the node is the proposer.
+    pub proposal: &'a [u8],
+    /// Votes the node should count in the height, including its own, one of each voter in
+    /// every round and vote type.
+    pub votes: &'a [Vote],
+    /// Votes of other heights or rounds sent in this round, which the node should not count.
+    pub stale_votes: &'a [Vote],
+    /// Proposals of other heights or rounds sent in this round.
+    pub stale_proposals: &'a [Vec<u8>],
+    /// Proposals of byzantine authorities, which should never be voted or committed.
+    pub byzantine: &'a [Vec<u8>],
+    /// Results accepted by height.
+    pub commits: &'a HashMap<u64, Vec<u8>>,
+    /// Whether the test case expects a commit, false in a `SHOULD_NOT_COMMIT` case.
+    pub expect_commit: bool,
+    /// Every message exchanged with the node in the run, or in the heights kept by
+    /// `Actuator::set_history_heights`. The last one is what the node just answered.
+    pub history: &'a [Event],
+    pub(crate) crypto: Option<&'a dyn Crypto>,
+}
+
+impl<'a> Context<'a> {
+    /// A function to get the message the node just answered.
+    pub fn last(&self) -> Option<&Event> {
+        self.history.last()
+    }
+
+    /// A function to get the vote the node just answered.
+    pub fn last_vote(&self) -> Option<&Vote> {
+        match self.last() {
+            Some(Event::Recv(FrameRecv::Vote(v))) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// A function to count the votes for each proposal in the round.
+    pub fn count(&self, round: u64, vote_type: VoteType) -> HashMap<&[u8], usize> {
+        let mut res = HashMap::new();
+        for vote in self
+            .votes
+            .iter()
+            .filter(|v| v.round == round && v.vote_type == vote_type)
+        {
+            *res.entry(vote.proposal.as_slice()).or_insert(0) += 1;
+        }
+        res
+    }
+
+    /// A function to check whether the number of votes is above 2/3 of the authorities.
+    pub fn is_above_threshold(&self, num: usize) -> bool {
+        num * 3 > self.authority_list.len() * 2
+    }
+
+    /// A function to check whether the proposal only reaches the threshold in the round if the
+    /// stale votes are counted.
+    pub fn is_stale_counted(&self, vote_type: VoteType, proposal: &[u8]) -> bool {
+        let stale = self
+            .stale_votes
+            .iter()
+            .filter(|v| v.vote_type == vote_type && v.proposal == proposal)
+            .count();
+        let count = self
+            .count(self.round, vote_type)
+            .get(proposal)
+            .cloned()
+            .unwrap_or(0);
+        stale > 0 && self.is_above_threshold(count + stale)
+    }
+
+    /// A function to verify the signature with the cryptography of the actuator, always true
+    /// without one.
+    pub fn verify(&self, hash: &[u8], signature: &[u8], address: &[u8]) -> bool {
+        match self.crypto {
+            Some(crypto) => crypto.verify(hash, signature, address),
+            None => true,
+        }
+    }
+}
+
+/// A safety property checked after every step the node answers.
+pub trait Invariant {
+    /// A function to check the property, return the violation if any.
+    fn check(&mut self, ctx: &Context) -> BftResult<()>;
+}
+
+/// A function to get the built-in invariants of Tendermint.
+pub fn builtin() -> Vec<Box<dyn Invariant>> {
+    vec![
+        Box::new(ValidProposal),
+        Box::new(ValidPrevote),
+        Box::new(ValidPrecommit),
+        Box::new(NoCommit),
+        Box::new(ValidCommit),
+    ]
+}
+
+/// The node proposes in the current height and round, the lock proposal if locked, otherwise
+/// the feed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ValidProposal;
+
+impl Invariant for ValidProposal {
+    fn check(&mut self, ctx: &Context) -> BftResult<()> {
+        let p = match ctx.last() {
+            Some(Event::Recv(FrameRecv::Proposal(p))) if ctx.step == Step::Propose => p,
+            _ => return Ok(()),
+        };
+        if p.height != ctx.height || p.round != ctx.round {
+            return Err(BftError::IllegalProposal(ctx.height, ctx.round));
+        }
+        if ctx.lock_round.is_some() {
+            if p.lock_round.is_none() || Some(p.content.as_slice()) != ctx.lock_proposal {
+                return Err(BftError::IllegalProposal(ctx.height, ctx.round));
+            }
+        } else if p.lock_round.is_some() || p.content != ctx.proposal {
+            return Err(BftError::IllegalProposal(ctx.height, ctx.round));
+        }
+        Ok(())
+    }
+}
+
+/// The node prevotes for the lock proposal if locked, otherwise the proposal of the round, or
+/// nil.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ValidPrevote;
+
+impl Invariant for ValidPrevote {
+    fn check(&mut self, ctx: &Context) -> BftResult<()> {
+        let vote = match ctx.last_vote() {
+            Some(v) if ctx.step == Step::Prevote => v,
+            _ => return Ok(()),
+        };
+        let proposal = ctx.lock_proposal.unwrap_or(ctx.proposal);
+        if ctx.stale_proposals.contains(&vote.proposal) {
+            return Err(BftError::StaleMsgCounted(ctx.height, ctx.round));
+        }
+        if !vote.proposal.is_empty() && vote.proposal != proposal {
+            return Err(BftError::IllegalVote(vote.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// The node precommits for a proposal only with +2/3 prevotes for it in the round, and for the
+/// proposal with +2/3 prevotes if any.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ValidPrecommit;
+
+impl Invariant for ValidPrecommit {
+    fn check(&mut self, ctx: &Context) -> BftResult<()> {
+        let vote = match ctx.last_vote() {
+            Some(v) if ctx.step == Step::Precommit => v,
+            _ => return Ok(()),
+        };
+        let prevotes = ctx.count(ctx.round, VoteType::Prevote);
+        if !vote.proposal.is_empty() {
+            let count = prevotes.get(vote.proposal.as_slice()).cloned().unwrap_or(0);
+            if !ctx.is_above_threshold(count) {
+                if ctx.is_stale_counted(VoteType::Prevote, &vote.proposal) {
+                    return Err(BftError::StaleMsgCounted(ctx.height, ctx.round));
+                }
+                return Err(BftError::ShouldNotPrecommit(ctx.height, ctx.round));
+            }
+        }
+        for (p, count) in prevotes.iter() {
+            if ctx.is_above_threshold(*count) && *p != vote.proposal.as_slice() {
+                return Err(BftError::PrecommitErr(ctx.height, ctx.round));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The node commits once in the height, a non-byzantine result with +2/3 precommits in a
+/// round, and the proof is +2/3 precommits of that round if any.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ValidCommit;
+
+impl Invariant for ValidCommit {
+    fn check(&mut self, ctx: &Context) -> BftResult<()> {
+        let commit = match ctx.last() {
+            Some(Event::Commit(c)) if ctx.step == Step::Commit => c,
+            _ => return Ok(()),
+        };
+        check_commit_height(ctx, commit)?;
+        check_commit_proof(ctx, commit)?;
+        if ctx.byzantine.contains(&commit.result) {
+            return Err(BftError::CommitIncorrect(ctx.height));
+        }
+
+        // the commit should be in a round with +2/3 precommits for the result
+        let mut rounds: Vec<u64> = ctx
+            .votes
+            .iter()
+            .filter(|v| v.vote_type == VoteType::Precommit)
+            .map(|v| v.round)
+            .collect();
+        rounds.sort_unstable();
+        rounds.dedup();
+        let rounds: Vec<u64> = rounds
+            .into_iter()
+            .filter(|r| {
+                let count = ctx.count(*r, VoteType::Precommit);
+                ctx.is_above_threshold(count.get(commit.result.as_slice()).cloned().unwrap_or(0))
+            })
+            .collect();
+        if rounds.is_empty() {
+            return Err(BftError::CommitIncorrect(ctx.height));
+        }
+        if let Some(vote) = commit.proof.first() {
+            if !rounds.contains(&vote.round) {
+                return Err(BftError::CommitIncorrect(ctx.height));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The node does not commit when the test case expects no commit, such as when the result only
+/// reaches +2/3 precommits if the stale ones are counted.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoCommit;
+
+impl Invariant for NoCommit {
+    fn check(&mut self, ctx: &Context) -> BftResult<()> {
+        let commit = match ctx.last() {
+            Some(Event::Commit(c)) if ctx.step == Step::Commit && !ctx.expect_commit => c,
+            _ => return Ok(()),
+        };
+        check_commit_height(ctx, commit)?;
+        if ctx.is_stale_counted(VoteType::Precommit, &commit.result) {
+            return Err(BftError::StaleMsgCounted(ctx.height, ctx.round));
+        }
+        Err(BftError::CommitInvalid(ctx.height))
+    }
+}
+
+fn check_commit_height(ctx: &Context, commit: &Commit) -> BftResult<()> {
+    if let Some(result) = ctx.commits.get(&commit.height) {
+        if result != &commit.result {
+            return Err(BftError::CommitDiff(commit.height));
+        }
+        return Err(BftError::MultipleCommit(commit.height));
+    }
+    if commit.height != ctx.height {
+        return Err(BftError::MislaidCommit(commit.height));
+    }
+    Ok(())
+}
+
+fn check_commit_proof(ctx: &Context, commit: &Commit) -> BftResult<()> {
+    if commit.proof.is_empty() {
+        return Ok(());
+    }
+    // +2/3 precommits for the result in the same round from different authorities
+    let round = commit.proof[0].round;
+    let mut voters = Vec::new();
+    for vote in commit.proof.iter() {
+        if vote.vote_type != VoteType::Precommit
+            || vote.height != commit.height
+            || vote.round != round
+            || vote.proposal != commit.result
+            || !ctx.authority_list.contains(&vote.voter)
+            || voters.contains(&vote.voter)
+            || !ctx.verify(&vote_hash(vote), &vote.signature, &vote.voter)
+        {
+            return Err(BftError::IllegalCommitProof(commit.height));
+        }
+        voters.push(vote.voter.clone());
+    }
+    if !ctx.is_above_threshold(voters.len()) {
+        return Err(BftError::IllegalCommitProof(commit.height));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testkit::{Mutation, ReferenceNode};
+    use crate::whitebox::{
+        actuator::Actuator,
+        correctness::test_case::{
+            lock_proposal, no_byzantine_cases, SHOULD_COMMIT, SHOULD_NOT_COMMIT,
+        },
+    };
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // the node commits no more than the given heights
+    struct HeightLimit(u64);
+
+    impl Invariant for HeightLimit {
+        fn check(&mut self, ctx: &Context) -> BftResult<()> {
+            match ctx.last() {
+                Some(Event::Commit(c)) if c.height >= self.0 => Err(BftError::Violation(format!(
+                    "commit at height {}",
+                    c.height
+                ))),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    fn authority_list() -> Vec<Vec<u8>> {
+        (0..4).map(|i| vec![i]).collect()
+    }
+
+    #[test]
+    fn test_user_invariant() {
+        let node = ReferenceNode::new(authority_list());
+        let mut actuator = Actuator::new(node, 0, 0, authority_list(), ":memory:").unwrap();
+        actuator.add_invariant(Box::new(HeightLimit(2)));
+        let err = actuator.proc_test(no_byzantine_cases()).unwrap_err();
+        match err.root() {
+            BftError::Violation(msg) => assert_eq!(msg, "commit at height 2"),
+            e => panic!("Unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_replace_invariants() {
+        let node = ReferenceNode::with_mutation(authority_list(), Mutation::IgnoreLock);
+        let mut actuator = Actuator::new(node, 0, 0, authority_list(), ":memory:").unwrap();
+        actuator.set_invariants(Vec::new());
+        actuator.proc_test(lock_proposal()).unwrap();
+    }
+
+    #[test]
+    fn test_replace_no_commit() {
+        // the node commits with +2/3 precommits where no commit is expected
+        let cases = vec![[1; 6], SHOULD_NOT_COMMIT, [1; 6], SHOULD_NOT_COMMIT];
+        let node = ReferenceNode::new(authority_list());
+        let mut actuator = Actuator::new(node, 0, 0, authority_list(), ":memory:").unwrap();
+        let err = actuator.proc_test(cases.clone()).unwrap_err();
+        assert!(matches!(err.root(), BftError::CommitInvalid(0)));
+
+        let node = ReferenceNode::new(authority_list());
+        let mut actuator = Actuator::new(node, 0, 0, authority_list(), ":memory:").unwrap();
+        actuator.set_invariants(vec![
+            Box::new(ValidProposal),
+            Box::new(ValidPrevote),
+            Box::new(ValidPrecommit),
+            Box::new(ValidCommit),
+        ]);
+        actuator.proc_test(cases).unwrap();
+    }
+
+    // the number of commits in the history at every commit
+    struct HistoryCommits(Rc<RefCell<Vec<usize>>>);
+
+    impl Invariant for HistoryCommits {
+        fn check(&mut self, ctx: &Context) -> BftResult<()> {
+            if let Some(Event::Commit(_)) = ctx.last() {
+                let commits = ctx
+                    .history
+                    .iter()
+                    .filter(|e| matches!(e, Event::Commit(_)))
+                    .count();
+                self.0.borrow_mut().push(commits);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_history_heights() {
+        let cases = vec![
+            [1; 6],
+            SHOULD_COMMIT,
+            [1; 6],
+            SHOULD_COMMIT,
+            [1; 6],
+            SHOULD_COMMIT,
+        ];
+        let commits = |heights: Option<u64>| {
+            let node = ReferenceNode::new(authority_list());
+            let mut actuator = Actuator::new(node, 0, 0, authority_list(), ":memory:").unwrap();
+            if let Some(heights) = heights {
+                actuator.set_history_heights(heights);
+            }
+            let counts = Rc::new(RefCell::new(Vec::new()));
+            actuator.add_invariant(Box::new(HistoryCommits(counts.clone())));
+            actuator.proc_test(cases.clone()).unwrap();
+            let counts = counts.borrow().clone();
+            counts
+        };
+        // the whole run by default
+        assert_eq!(commits(None), vec![1, 2, 3]);
+        assert_eq!(commits(Some(2)), vec![1, 2, 2]);
+        assert_eq!(commits(Some(1)), vec![1, 1, 1]);
+    }
+}
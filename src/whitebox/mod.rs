@@ -8,117 +8,146 @@ pub type BftResult<T> = Result<T, BftError>;
 /// Test framework result.
 pub type FrameResult<T> = Result<T, FrameError>;
 
-///
+/// A message the node answers to the actuator.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum FrameRecv {
-    ///
+    /// A proposal of the node when it is the proposer.
     Proposal(Proposal),
-    ///
+    /// A prevote or precommit of the node.
     Vote(Vote),
 }
 
-///
+/// A message the actuator sends to the node.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum FrameSend {
-    ///
+    /// A proposal of another authority.
     Proposal(Proposal),
-    ///
+    /// A prevote or precommit of another authority.
     Vote(Vote),
-    ///
+    /// The content for the node to propose.
     Feed(Feed),
-    ///
+    /// The height to work on and the authority list.
     Status(Status),
 }
 
-///
+/// A proposal of a height and round.
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, Eq, PartialEq)]
 pub struct Proposal {
-    ///
+    /// Height of the proposal.
     pub height: u64,
-    ///
+    /// Round of the proposal.
     pub round: u64,
-    ///
+    /// Hash of the proposed content.
     pub content: Hash,
-    ///
+    /// Address of the proposer.
     pub proposer: Address,
-    ///
+    /// Round the proposer is locked in, `None` if not locked.
     pub lock_round: Option<u64>,
-    ///
+    /// Prevotes of the lock round for the content, which prove the lock.
     pub lock_votes: Vec<Vote>,
+    /// Signature of the proposer, empty without a cryptography.
+    #[serde(default)]
+    pub signature: Vec<u8>,
 }
 
-///
+/// A prevote or precommit of a height and round.
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, Eq, PartialEq)]
 pub struct Vote {
-    ///
+    /// Height of the vote.
     pub height: u64,
-    ///
+    /// Round of the vote.
     pub round: u64,
-    ///
+    /// Whether it is a prevote or a precommit.
     pub vote_type: VoteType,
-    ///
+    /// Hash of the proposal voted for, empty for nil.
     pub proposal: Hash,
-    ///
+    /// Address of the voter.
     pub voter: Address,
+    /// Signature of the voter, empty without a cryptography.
+    #[serde(default)]
+    pub signature: Vec<u8>,
 }
 
-///
+/// A result the node commits.
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, Eq, PartialEq)]
 pub struct Commit {
-    ///
+    /// Index of the committing node in the authority list.
     pub node: u8,
-    ///
+    /// Height of the commit.
     pub height: u64,
-    ///
+    /// Hash of the committed proposal.
     pub result: Vec<u8>,
+    /// Precommits the node commits with, an empty proof is not checked.
+    #[serde(default)]
+    pub proof: Vec<Vote>,
 }
 
-///
+/// The content for the node to propose at a height.
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, Eq, PartialEq)]
 pub struct Feed {
-    ///
+    /// Height of the feed.
     pub height: u64,
-    ///
+    /// Hash of the content to propose.
     pub proposal: Vec<u8>,
 }
 
-///
+/// The height for the node to work on and the authority list, sent at the start of a test and
+/// after every commit.
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, Eq, PartialEq)]
 pub struct Status {
-    ///
+    /// Height to work on.
     pub height: u64,
-    ///
+    /// Addresses of the authorities, the node is the first one.
     pub authority_list: Vec<Address>,
 }
 
-///
+/// Type of a vote.
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, Eq, PartialEq)]
 pub enum VoteType {
-    ///
+    /// A vote of the first voting step.
     Prevote,
-    ///
+    /// A vote of the second voting step.
     Precommit,
 }
 
-///
+/// The interface of the node under test, implemented to connect the node to the actuator.
 pub trait Support {
-    ///
+    /// A function to send a message to the node.
     fn send(&self, msg: FrameSend);
-    ///
+    /// A function to receive the next proposal or vote of the node, blocking until it answers.
     fn recv(&self) -> FrameRecv;
-    ///
+    /// A function to get a commit of the node if any, without blocking.
     fn try_get_commit(&self) -> Option<Commit>;
-    ///
+    /// A function to stop the node.
     fn stop(&self);
-    ///
+    /// A function to get the index in the authority list of the proposer at the height and
+    /// round elected by the node.
     fn cal_proposer(&self, height: u64, round: u64) -> usize;
 }
 
-///
+/// The actuator running the test cases against the node.
 pub mod actuator;
-///
+/// Auditing the safety properties of a recorded run.
+pub mod audit;
+/// Recording, querying and exporting the messages of a run.
 pub mod collection;
-///
+/// Test cases and reference algorithms.
 pub mod correctness;
-///
+/// Signatures of the proposals and votes.
+pub mod crypto;
+/// Differences between the messages of two runs.
+pub mod diff;
+/// Errors of the node and of the test framework.
 pub mod error;
+/// Safety properties checked after every step.
+pub mod invariant;
+/// Observers of the messages and the state of the actuator.
+pub mod observer;
+/// Replaying a recorded run against the node.
+pub mod replay;
+/// Duplicating, delaying and reordering the delivery of messages.
+pub mod scheduler;
+/// Sequence diagrams of a run.
+pub mod sequence;
+/// Timelines of a run.
+pub mod timeline;
@@ -0,0 +1,122 @@
+use crate::whitebox::{error::BftError, Commit, FrameRecv, FrameSend};
+use serde_derive::{Deserialize, Serialize};
+
+/// Steps of the actuator in a round.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Step {
+    /// Feed the node or send the proposal to it, and check the proposal of the node.
+    Propose,
+    /// Send the prevotes and check the prevote of the node.
+    Prevote,
+    /// Send the precommits and check the precommit of the node.
+    Precommit,
+    /// Check whether the node commits.
+    Commit,
+}
+
+impl Step {
+    pub(crate) fn from_name(name: &str) -> Option<Step> {
+        match name {
+            "Propose" => Some(Step::Propose),
+            "Prevote" => Some(Step::Prevote),
+            "Precommit" => Some(Step::Precommit),
+            "Commit" => Some(Step::Commit),
+            _ => None,
+        }
+    }
+}
+
+/// An observer of the actuator. Every callback does nothing by default, so an observer only
+/// implements what it cares about.
+pub trait Observer {
+    /// Called when a message is delivered to the node, after any duplication or reordering of
+    /// the delivery.
+    fn on_send(&mut self, _msg: &FrameSend) {}
+    /// Called when a message is received from the node, before it is checked.
+    fn on_recv(&mut self, _msg: &FrameRecv) {}
+    /// Called when a commit is received from the node, before it is checked.
+    fn on_commit(&mut self, _commit: &Commit) {}
+    /// Called when the actuator enters a step.
+    fn on_step(&mut self, _height: u64, _round: u64, _step: Step) {}
+    /// Called when the actuator starts a new height or round.
+    fn on_height_round(&mut self, _height: u64, _round: u64) {}
+    /// Called when the lock of the actuator changes, with the lock round and proposal, or
+    /// `None` when unlocked.
+    fn on_lock(&mut self, _lock: Option<(u64, &[u8])>) {}
+    /// Called when a test case fails.
+    fn on_error(&mut self, _err: &BftError) {}
+}
+
+pub(crate) fn notify<F: FnMut(&mut dyn Observer)>(observers: &mut [Box<dyn Observer>], mut f: F) {
+    for observer in observers.iter_mut() {
+        f(observer.as_mut());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testkit::ReferenceNode;
+    use crate::whitebox::{actuator::Actuator, correctness::test_case::lock_proposal};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Record {
+        sent: usize,
+        received: usize,
+        commits: Vec<u64>,
+        steps: usize,
+        heights: Vec<u64>,
+        locks: usize,
+    }
+
+    struct Recorder(Rc<RefCell<Record>>);
+
+    impl Observer for Recorder {
+        fn on_send(&mut self, _msg: &FrameSend) {
+            self.0.borrow_mut().sent += 1;
+        }
+
+        fn on_recv(&mut self, _msg: &FrameRecv) {
+            self.0.borrow_mut().received += 1;
+        }
+
+        fn on_commit(&mut self, commit: &Commit) {
+            self.0.borrow_mut().commits.push(commit.height);
+        }
+
+        fn on_step(&mut self, _height: u64, _round: u64, _step: Step) {
+            self.0.borrow_mut().steps += 1;
+        }
+
+        fn on_height_round(&mut self, height: u64, round: u64) {
+            if round == 0 {
+                self.0.borrow_mut().heights.push(height);
+            }
+        }
+
+        fn on_lock(&mut self, _lock: Option<(u64, &[u8])>) {
+            self.0.borrow_mut().locks += 1;
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let node = ReferenceNode::new(authority_list.clone());
+        let mut actuator = Actuator::new(node, 0, 0, authority_list, ":memory:").unwrap();
+        let record = Rc::new(RefCell::new(Record::default()));
+        actuator.add_observer(Box::new(Recorder(record.clone())));
+        actuator.proc_test(lock_proposal()).unwrap();
+
+        let record = record.borrow();
+        assert!(record.sent > 0 && record.received > 0 && record.steps > 0);
+        assert!(record.locks > 0);
+        // a new height starts after every commit
+        assert_eq!(record.heights.len(), record.commits.len() + 1);
+        for (i, height) in record.commits.iter().enumerate() {
+            assert_eq!(*height, i as u64);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use crate::whitebox::{
+    collection::{
+        query::{Filter, TraceReader},
+        storage::Direction,
+        util::Msg,
+    },
+    *,
+};
+
+/// The first difference between the node and a recorded run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// Sequence number of the recorded message.
+    pub seq: u64,
+    /// What the node answered in the run, `None` for a commit poll which got nothing.
+    pub expected: Option<Msg>,
+    /// What the node answers in the replay, `None` for a commit poll which gets nothing.
+    pub actual: Option<Msg>,
+}
+
+// a recorded call to the node
+enum Call {
+    Send(FrameSend),
+    Recv(Msg),
+    Poll,
+}
+
+/// A function to replay a recorded run against the node. The messages sent to the node are
+/// recorded as delivered, so they are sent again in the recorded order with any duplication,
+/// delay or reordering of the delivery, and every answer of the node is compared with the
+/// recorded one. Return the first divergence if any.
+pub fn replay<T: Support>(
+    node: &T,
+    reader: &TraceReader,
+    run_id: i64,
+) -> FrameResult<Option<Divergence>> {
+    let mut calls: Vec<(u64, Call)> = Vec::new();
+    for record in reader.load(run_id, &Filter::default())?.into_iter() {
+        let call = match (record.direction, record.msg) {
+            (Direction::Send, Msg::Proposal(p)) => Call::Send(FrameSend::Proposal(p)),
+            (Direction::Send, Msg::Vote(v)) => Call::Send(FrameSend::Vote(v)),
+            (Direction::Send, Msg::Feed(f)) => Call::Send(FrameSend::Feed(f)),
+            (Direction::Send, Msg::Status(s)) => Call::Send(FrameSend::Status(s)),
+            (_, msg) => Call::Recv(msg),
+        };
+        calls.push((record.seq, call));
+    }
+    for poll in reader.polls(run_id)?.into_iter() {
+        calls.push((poll.seq, Call::Poll));
+    }
+    calls.sort_by_key(|(seq, _)| *seq);
+
+    for (seq, call) in calls.into_iter() {
+        let (expected, actual) = match call {
+            Call::Send(msg) => {
+                node.send(msg);
+                continue;
+            }
+            Call::Recv(Msg::Commit(c)) => {
+                (Some(Msg::Commit(c)), node.try_get_commit().map(Msg::Commit))
+            }
+            Call::Recv(msg) => {
+                let actual = match node.recv() {
+                    FrameRecv::Proposal(p) => Msg::Proposal(p),
+                    FrameRecv::Vote(v) => Msg::Vote(v),
+                };
+                (Some(msg), Some(actual))
+            }
+            Call::Poll => (None, node.try_get_commit().map(Msg::Commit)),
+        };
+        if expected != actual {
+            return Ok(Some(Divergence {
+                seq,
+                expected,
+                actual,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testkit::{Mutation, ReferenceNode};
+    use crate::whitebox::{
+        actuator::Actuator, collection::storage::remove_db, correctness::test_case::lock_proposal,
+        scheduler::Delivery,
+    };
+    use rand::random;
+
+    #[test]
+    fn test_replay() {
+        let path = std::env::temp_dir().join(format!("bft_replay_{}.db", random::<u64>()));
+        let path = path.to_str().unwrap();
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let node = ReferenceNode::new(authority_list.clone());
+        let mut actuator = Actuator::new(node, 0, 0, authority_list.clone(), path).unwrap();
+        actuator.proc_test(lock_proposal()).unwrap();
+        let run_id = actuator.run_id();
+
+        let reader = TraceReader::open(path).unwrap();
+        let node = ReferenceNode::new(authority_list.clone());
+        assert_eq!(replay(&node, &reader, run_id).unwrap(), None);
+
+        // a node ignoring the lock diverges once it is locked
+        let node = ReferenceNode::with_mutation(authority_list, Mutation::IgnoreLock);
+        let divergence = replay(&node, &reader, run_id).unwrap().unwrap();
+        assert_ne!(divergence.expected, divergence.actual);
+        remove_db(path);
+    }
+
+    #[test]
+    fn test_replay_delivery() {
+        let path = std::env::temp_dir().join(format!("bft_replay_{}.db", random::<u64>()));
+        let path = path.to_str().unwrap();
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let node = ReferenceNode::new(authority_list.clone());
+        let mut actuator = Actuator::new(node, 0, 0, authority_list.clone(), path).unwrap();
+        actuator
+            .set_delivery(Delivery {
+                seed: 7,
+                duplicate: 0.3,
+                delay: 0.0,
+                reorder: true,
+            })
+            .unwrap();
+        actuator.proc_test(lock_proposal()).unwrap();
+
+        // the duplicated and reordered messages are replayed as delivered
+        let reader = TraceReader::open(path).unwrap();
+        let sent: Vec<Msg> = reader
+            .load(actuator.run_id(), &Filter::default())
+            .unwrap()
+            .into_iter()
+            .filter(|r| r.direction == Direction::Send)
+            .map(|r| r.msg)
+            .collect();
+        assert!((1..sent.len()).any(|i| sent[i..].contains(&sent[i - 1])));
+        let node = ReferenceNode::new(authority_list);
+        assert_eq!(replay(&node, &reader, actuator.run_id()).unwrap(), None);
+        remove_db(path);
+    }
+}
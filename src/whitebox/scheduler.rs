@@ -0,0 +1,171 @@
+use crate::whitebox::{error::FrameError, FrameResult, FrameSend};
+use rand::{prng::XorShiftRng, Rng, SeedableRng};
+
+/// Configuration of the message delivery between the actuator and the node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delivery {
+    /// Seed of the delivery, the same seed gives the same delivery and random proposals.
+    pub seed: u64,
+    /// Probability of sending a message twice.
+    pub duplicate: f64,
+    /// Probability of delaying a vote to the next step.
+    pub delay: f64,
+    /// Whether to shuffle the messages of a step.
+    pub reorder: bool,
+}
+
+impl Delivery {
+    /// A function to create a new delivery with the seed, which does not duplicate, delay or
+    /// reorder any message.
+    pub fn new(seed: u64) -> Self {
+        Delivery {
+            seed,
+            duplicate: 0.0,
+            delay: 0.0,
+            reorder: false,
+        }
+    }
+
+    pub(crate) fn check(&self) -> FrameResult<()> {
+        for p in [self.duplicate, self.delay].iter() {
+            if !(0.0..=1.0).contains(p) {
+                return Err(FrameError::InvalidDelivery(*p));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Messages are buffered until the actuator is going to receive from the node or to check the
+// commit, then delivered by the delivery configuration.
+pub(crate) struct Scheduler {
+    delivery: Delivery,
+    rng: XorShiftRng,
+    current: Vec<FrameSend>,
+    delayed: Vec<FrameSend>,
+}
+
+impl Scheduler {
+    pub(crate) fn new(delivery: Delivery) -> Self {
+        Scheduler {
+            rng: XorShiftRng::seed_from_u64(delivery.seed),
+            delivery,
+            current: Vec::new(),
+            delayed: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, msg: FrameSend) {
+        self.current.push(msg);
+    }
+
+    // Get the messages to deliver in this step, votes may be delayed to the next step.
+    pub(crate) fn step(&mut self) -> Vec<FrameSend> {
+        let mut msgs: Vec<FrameSend> = self.delayed.drain(..).collect();
+        for msg in self.current.drain(..) {
+            if matches!(msg, FrameSend::Vote(_)) && self.rng.gen_bool(self.delivery.delay) {
+                self.delayed.push(msg);
+            } else {
+                msgs.push(msg);
+            }
+        }
+        self.deliver(msgs)
+    }
+
+    // Get all the buffered messages, nothing is delayed.
+    pub(crate) fn flush(&mut self) -> Vec<FrameSend> {
+        let mut msgs: Vec<FrameSend> = self.delayed.drain(..).collect();
+        msgs.append(&mut self.current);
+        self.deliver(msgs)
+    }
+
+    fn deliver(&mut self, mut msgs: Vec<FrameSend>) -> Vec<FrameSend> {
+        if self.delivery.reorder {
+            self.rng.shuffle(&mut msgs);
+        }
+        let mut res = Vec::with_capacity(msgs.len());
+        for msg in msgs.into_iter() {
+            if self.rng.gen_bool(self.delivery.duplicate) {
+                res.push(msg.clone());
+            }
+            res.push(msg);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::whitebox::{Feed, Vote, VoteType};
+
+    fn generate_msg() -> Vec<FrameSend> {
+        let mut msg = vec![FrameSend::Feed(Feed {
+            height: 1,
+            proposal: vec![1, 2, 3],
+        })];
+        for i in 0..3 {
+            msg.push(FrameSend::Vote(Vote {
+                height: 1,
+                round: 0,
+                vote_type: VoteType::Prevote,
+                proposal: vec![1, 2, 3],
+                voter: vec![i],
+                signature: Vec::new(),
+            }));
+        }
+        msg
+    }
+
+    fn run(delivery: Delivery) -> Vec<Vec<FrameSend>> {
+        let mut scheduler = Scheduler::new(delivery);
+        let mut res = Vec::new();
+        for msg in generate_msg().into_iter() {
+            scheduler.push(msg);
+        }
+        res.push(scheduler.step());
+        res.push(scheduler.flush());
+        res
+    }
+
+    #[test]
+    fn test_default_delivery() {
+        let res = run(Delivery::new(0));
+        assert_eq!(res[0], generate_msg());
+        assert!(res[1].is_empty());
+    }
+
+    #[test]
+    fn test_seed_delivery() {
+        let delivery = Delivery {
+            seed: 42,
+            duplicate: 0.5,
+            delay: 0.5,
+            reorder: true,
+        };
+        let res = run(delivery.clone());
+        assert_eq!(res, run(delivery));
+        // every message is delivered at least once
+        for msg in generate_msg().iter() {
+            assert!(res.iter().any(|step| step.contains(msg)));
+        }
+        // the feed is never delayed
+        assert!(res[0].contains(&generate_msg()[0]));
+    }
+
+    #[test]
+    fn test_invalid_delivery() {
+        let delivery = |duplicate, delay| Delivery {
+            duplicate,
+            delay,
+            ..Delivery::new(0)
+        };
+        assert!(delivery(0.0, 1.0).check().is_ok());
+        for (duplicate, delay) in [(1.5, 0.0), (0.0, -0.1), (f64::NAN, 0.0)].iter() {
+            match delivery(*duplicate, *delay).check() {
+                Err(FrameError::InvalidDelivery(_)) => (),
+                res => panic!("Unexpected result {:?}", res),
+            }
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use crate::whitebox::{
+    collection::{
+        query::{Filter, Record, TraceReader},
+        storage::Direction,
+        util::Msg,
+    },
+    timeline::short,
+    *,
+};
+
+use std::fmt::Write;
+
+// lifeline of the feeds, status and commits
+const HARNESS: &str = "H";
+
+/// Syntax of a sequence diagram.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Mermaid `sequenceDiagram`.
+    Mermaid,
+    /// PlantUML between `@startuml` and `@enduml`.
+    PlantUml,
+}
+
+/// A sequence diagram of the messages between the actuator and the node, with a lifeline per
+/// authority and one for the harness, which sends the feeds and status and gets the commits.
+/// The node is the first authority, every message sent to it comes from the voter or proposer,
+/// and every proposal and vote it answers is drawn to each of the other authorities.
+pub struct SequenceDiagram {
+    authority_list: Vec<Address>,
+    format: Format,
+    // first and last height and round
+    range: Option<((u64, u64), (u64, u64))>,
+}
+
+impl SequenceDiagram {
+    /// A function to create a diagram of every message.
+    pub fn new(authority_list: Vec<Vec<u8>>, format: Format) -> Self {
+        SequenceDiagram {
+            authority_list,
+            format,
+            range: None,
+        }
+    }
+
+    /// A function to create a diagram of the messages from height and round `from` to `to`,
+    /// both inclusive. Commits, feeds and status have no round, so they are shown if the height
+    /// is in the range.
+    pub fn with_range(
+        authority_list: Vec<Vec<u8>>,
+        format: Format,
+        from: (u64, u64),
+        to: (u64, u64),
+    ) -> Self {
+        SequenceDiagram {
+            authority_list,
+            format,
+            range: Some((from, to)),
+        }
+    }
+
+    /// A function to render the messages of the run in the storage.
+    pub fn render_run(&self, reader: &TraceReader, run_id: i64) -> FrameResult<String> {
+        Ok(self.render(&reader.load(run_id, &Filter::default())?))
+    }
+
+    /// A function to render the messages in the order given, such as the records of a
+    /// `MemoryStorage` of a running actuator.
+    pub fn render(&self, records: &[Record]) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}",
+            match self.format {
+                Format::Mermaid => "sequenceDiagram",
+                Format::PlantUml => "@startuml",
+            }
+        );
+        for (i, address) in self.authority_list.iter().enumerate() {
+            let name = if i == 0 {
+                format!("{} (node)", short(address))
+            } else {
+                short(address)
+            };
+            self.participant(&mut out, &lifeline(i), &name);
+        }
+        self.participant(&mut out, HARNESS, "harness");
+
+        let mut current = None;
+        for record in records.iter().filter(|r| self.is_in_range(&r.msg)) {
+            let height_round = match &record.msg {
+                Msg::Proposal(p) => Some((p.height, p.round)),
+                Msg::Vote(v) => Some((v.height, v.round)),
+                _ => None,
+            };
+            if let Some((height, round)) = height_round {
+                if current != height_round {
+                    current = height_round;
+                    self.note(&mut out, &format!("H{} R{}", height, round));
+                }
+            }
+
+            let label = label(&record.msg);
+            match record.direction {
+                Direction::Send => {
+                    let from = match &record.msg {
+                        Msg::Proposal(p) => self.lifeline_of(&p.proposer),
+                        Msg::Vote(v) => self.lifeline_of(&v.voter),
+                        _ => HARNESS.to_string(),
+                    };
+                    self.arrow(&mut out, &from, &lifeline(0), &label);
+                }
+                Direction::Recv => match &record.msg {
+                    Msg::Proposal(_) | Msg::Vote(_) => {
+                        for i in 1..self.authority_list.len() {
+                            self.arrow(&mut out, &lifeline(0), &lifeline(i), &label);
+                        }
+                    }
+                    _ => self.arrow(&mut out, &lifeline(0), HARNESS, &label),
+                },
+            }
+        }
+        if self.format == Format::PlantUml {
+            let _ = writeln!(out, "@enduml");
+        }
+        out
+    }
+
+    fn is_in_range(&self, msg: &Msg) -> bool {
+        let (from, to) = match self.range {
+            Some(range) => range,
+            None => return true,
+        };
+        match msg {
+            Msg::Proposal(p) => from <= (p.height, p.round) && (p.height, p.round) <= to,
+            Msg::Vote(v) => from <= (v.height, v.round) && (v.height, v.round) <= to,
+            Msg::Commit(c) => from.0 <= c.height && c.height <= to.0,
+            Msg::Feed(f) => from.0 <= f.height && f.height <= to.0,
+            Msg::Status(s) => from.0 <= s.height && s.height <= to.0,
+        }
+    }
+
+    // messages of non-authorities come from the harness
+    fn lifeline_of(&self, address: &[u8]) -> String {
+        self.authority_list
+            .iter()
+            .position(|a| a.as_slice() == address)
+            .map(lifeline)
+            .unwrap_or_else(|| HARNESS.to_string())
+    }
+
+    fn participant(&self, out: &mut String, id: &str, name: &str) {
+        let _ = match self.format {
+            Format::Mermaid => writeln!(out, "    participant {} as {}", id, name),
+            Format::PlantUml => writeln!(out, "participant \"{}\" as {}", name, id),
+        };
+    }
+
+    fn note(&self, out: &mut String, text: &str) {
+        let (first, last) = (lifeline(0), HARNESS);
+        let _ = match self.format {
+            Format::Mermaid => writeln!(out, "    Note over {},{}: {}", first, last, text),
+            Format::PlantUml => writeln!(out, "note over {}, {} : {}", first, last, text),
+        };
+    }
+
+    fn arrow(&self, out: &mut String, from: &str, to: &str, label: &str) {
+        let _ = match self.format {
+            Format::Mermaid => writeln!(out, "    {}->>{}: {}", from, to, label),
+            Format::PlantUml => writeln!(out, "{} -> {} : {}", from, to, label),
+        };
+    }
+}
+
+fn lifeline(index: usize) -> String {
+    format!("A{}", index)
+}
+
+fn hash_label(hash: &[u8]) -> String {
+    if hash.is_empty() {
+        "nil".to_string()
+    } else {
+        short(hash)
+    }
+}
+
+fn label(msg: &Msg) -> String {
+    match msg {
+        Msg::Proposal(p) => format!(
+            "Proposal H{} R{} {}",
+            p.height,
+            p.round,
+            hash_label(&p.content)
+        ),
+        Msg::Vote(v) => format!(
+            "{:?} H{} R{} {}",
+            v.vote_type,
+            v.height,
+            v.round,
+            hash_label(&v.proposal)
+        ),
+        Msg::Commit(c) => format!("Commit H{} {}", c.height, hash_label(&c.result)),
+        Msg::Feed(f) => format!("Feed H{} {}", f.height, hash_label(&f.proposal)),
+        Msg::Status(s) => format!("Status H{}", s.height),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testkit::ReferenceNode;
+    use crate::whitebox::{
+        actuator::Actuator, collection::backend::MemoryStorage,
+        correctness::test_case::lock_proposal,
+    };
+
+    #[test]
+    fn test_sequence_diagram() {
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let node = ReferenceNode::new(authority_list.clone());
+        let storage = MemoryStorage::new();
+        let mut actuator = Actuator::with_storage(
+            node,
+            0,
+            0,
+            authority_list.clone(),
+            Box::new(storage.clone()),
+        )
+        .unwrap();
+        actuator.proc_test(lock_proposal()).unwrap();
+        let records = storage.records();
+
+        let mermaid =
+            SequenceDiagram::new(authority_list.clone(), Format::Mermaid).render(&records);
+        assert!(mermaid.starts_with("sequenceDiagram\n"));
+        assert_eq!(mermaid.matches("participant").count(), 5);
+        assert!(mermaid.contains("    A1->>A0: Prevote"));
+        assert!(mermaid.contains("    A0->>H: Commit"));
+
+        let plantuml = SequenceDiagram::new(authority_list.clone(), Format::PlantUml);
+        let plantuml = plantuml.render(&records);
+        assert!(plantuml.starts_with("@startuml\n"));
+        assert!(plantuml.ends_with("@enduml\n"));
+        assert!(plantuml.contains("A0 -> A3 : Prevote"));
+
+        // only the first round of the first proposal
+        let (height, round) = records
+            .iter()
+            .find_map(|r| match &r.msg {
+                Msg::Proposal(p) => Some((p.height, p.round)),
+                _ => None,
+            })
+            .unwrap();
+        let diagram = SequenceDiagram::with_range(
+            authority_list,
+            Format::Mermaid,
+            (height, round),
+            (height, round),
+        )
+        .render(&records);
+        assert_eq!(diagram.matches("Note over").count(), 1);
+        assert!(diagram.contains(&format!("Proposal H{} R{}", height, round)));
+        assert!(diagram.lines().count() < mermaid.lines().count());
+    }
+}
@@ -0,0 +1,396 @@
+use crate::whitebox::{
+    collection::{
+        query::{Filter, TraceReader},
+        storage::Direction,
+        util::Msg,
+    },
+    correctness::test_case::byzantine_proposal,
+    error::BftError,
+    observer::Step,
+    *,
+};
+
+use std::fmt::Write;
+
+const CELL_WIDTH: usize = 90;
+const ROW_HEIGHT: usize = 22;
+const LABEL_WIDTH: usize = 110;
+
+// colours of normal, byzantine, nil and offline votes
+const NORMAL: &str = "#8fd18f";
+const BYZANTINE: &str = "#e07070";
+const NIL: &str = "#d8d8d8";
+const OFFLINE: &str = "#ffffff";
+const ERROR: &str = "#d00000";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum LockChange {
+    Lock(Vec<u8>),
+    Unlock,
+}
+
+// what happens in a height and round
+#[derive(Clone, Debug, Default)]
+struct Column {
+    height: u64,
+    round: u64,
+    proposal: Option<Proposal>,
+    // by the index in the authority list, `None` if offline
+    prevotes: Vec<Option<Vec<u8>>>,
+    precommits: Vec<Option<Vec<u8>>>,
+    lock: Option<LockChange>,
+    commit: Option<Vec<u8>>,
+}
+
+/// A timeline of a recorded run, with the proposal, the prevote and precommit of every
+/// authority, the lock changes and the commit in every height and round the node takes part
+/// in. Messages of other heights and rounds, such as stale ones, are not shown, and only the
+/// first vote of an authority in a round and vote type is shown.
+pub struct Timeline {
+    run_id: i64,
+    authority_list: Vec<Address>,
+    columns: Vec<Column>,
+    error: Option<String>,
+    // height, round and step of the error
+    error_at: Option<(u64, u64, Option<Step>)>,
+}
+
+impl Timeline {
+    /// A function to load the timeline of the run.
+    pub fn load(
+        reader: &TraceReader,
+        run_id: i64,
+        authority_list: Vec<Vec<u8>>,
+    ) -> FrameResult<Self> {
+        let records = reader.load(run_id, &Filter::default())?;
+        let mut timeline = Timeline {
+            run_id,
+            authority_list,
+            columns: Vec::new(),
+            error: None,
+            error_at: None,
+        };
+
+        // the heights and rounds the node answers in
+        for record in records.iter().filter(|r| r.direction == Direction::Recv) {
+            match &record.msg {
+                Msg::Proposal(p) => timeline.add_column(p.height, p.round),
+                Msg::Vote(v) => timeline.add_column(v.height, v.round),
+                _ => (),
+            }
+        }
+        for poll in reader.polls(run_id)?.iter() {
+            timeline.add_column(poll.height, poll.round);
+        }
+        timeline.columns.sort_by_key(|c| (c.height, c.round));
+
+        for record in records.into_iter() {
+            timeline.add(record.msg);
+        }
+        timeline.set_locks();
+        Ok(timeline)
+    }
+
+    /// A function to highlight the step where the error is raised, by the context of the
+    /// error. An error without a context is only shown as the message.
+    pub fn highlight(&mut self, err: &BftError) {
+        self.error = Some(err.root().to_string());
+        self.error_at = err.context().map(|ctx| (ctx.height, ctx.round, ctx.step));
+    }
+
+    fn add_column(&mut self, height: u64, round: u64) {
+        if self.find(height, round).is_none() {
+            let n = self.authority_list.len();
+            self.columns.push(Column {
+                height,
+                round,
+                prevotes: vec![None; n],
+                precommits: vec![None; n],
+                ..Column::default()
+            });
+        }
+    }
+
+    fn find(&mut self, height: u64, round: u64) -> Option<&mut Column> {
+        self.columns
+            .iter_mut()
+            .find(|c| c.height == height && c.round == round)
+    }
+
+    fn add(&mut self, msg: Msg) {
+        match msg {
+            Msg::Proposal(p) => {
+                if !self.authority_list.contains(&p.proposer) {
+                    return;
+                }
+                if let Some(column) = self.find(p.height, p.round) {
+                    column.proposal.get_or_insert(p);
+                }
+            }
+            Msg::Vote(v) => {
+                let index = match self.authority_list.iter().position(|a| *a == v.voter) {
+                    Some(index) => index,
+                    None => return,
+                };
+                if let Some(column) = self.find(v.height, v.round) {
+                    let votes = match v.vote_type {
+                        VoteType::Prevote => &mut column.prevotes,
+                        VoteType::Precommit => &mut column.precommits,
+                    };
+                    votes[index].get_or_insert(v.proposal);
+                }
+            }
+            Msg::Commit(c) => {
+                // in the last round of the height
+                if let Some(column) = self
+                    .columns
+                    .iter_mut()
+                    .rev()
+                    .find(|column| column.height == c.height)
+                {
+                    column.commit.get_or_insert(c.result);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // lock on the proposal with +2/3 prevotes in a round, unlock with +2/3 prevotes for nil
+    fn set_locks(&mut self) {
+        let n = self.authority_list.len();
+        let mut lock: Option<(u64, Option<Vec<u8>>)> = None;
+        for column in self.columns.iter_mut() {
+            if lock.as_ref().is_some_and(|(h, _)| *h != column.height) {
+                lock = None;
+            }
+            let mut counts: Vec<(&Vec<u8>, usize)> = Vec::new();
+            for p in column.prevotes.iter().flatten() {
+                match counts.iter_mut().find(|(q, _)| *q == p) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((p, 1)),
+                }
+            }
+            let quorum = counts
+                .into_iter()
+                .find(|(_, count)| count * 3 > n * 2)
+                .map(|(p, _)| p.clone());
+            let current = lock.as_ref().and_then(|(_, p)| p.clone());
+            match quorum {
+                Some(p) if p.is_empty() => {
+                    if current.is_some() {
+                        column.lock = Some(LockChange::Unlock);
+                    }
+                    lock = Some((column.height, None));
+                }
+                Some(p) => {
+                    if current.as_ref() != Some(&p) {
+                        column.lock = Some(LockChange::Lock(p.clone()));
+                    }
+                    lock = Some((column.height, Some(p)));
+                }
+                None => (),
+            }
+        }
+    }
+
+    fn is_error(&self, column: &Column, step: Step) -> bool {
+        match self.error_at {
+            // an error out of any step is marked on every step of the round
+            Some((h, r, None)) => h == column.height && r == column.round,
+            Some((h, r, Some(s))) => h == column.height && r == column.round && s == step,
+            None => false,
+        }
+    }
+
+    /// A function to render the timeline as SVG.
+    pub fn to_svg(&self) -> String {
+        let n = self.authority_list.len();
+        // header, proposal, prevotes, precommits, lock, commit and the legend
+        let rows = 2 * n + 5;
+        // wide enough for the legend of 5 cells
+        let width = (LABEL_WIDTH + CELL_WIDTH * self.columns.len()).max(LABEL_WIDTH * 5);
+        let height = ROW_HEIGHT * (rows + 1);
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="monospace" font-size="11">"#,
+            width, height
+        );
+
+        let mut labels = vec!["".to_string(), "proposal".to_string()];
+        labels.extend((0..n).map(|i| format!("prevote {}", i)));
+        labels.extend((0..n).map(|i| format!("precommit {}", i)));
+        labels.push("lock".to_string());
+        labels.push("commit".to_string());
+        for (row, label) in labels.iter().enumerate() {
+            text(&mut svg, 4, row, label);
+        }
+
+        let byzantine = byzantine_proposal();
+        let vote_cell = |p: &Option<Vec<u8>>| match p {
+            None => (OFFLINE, "offline".to_string()),
+            Some(p) if p.is_empty() => (NIL, "nil".to_string()),
+            Some(p) if byzantine.contains(p) => (BYZANTINE, short(p)),
+            Some(p) => (NORMAL, short(p)),
+        };
+        for (i, column) in self.columns.iter().enumerate() {
+            let x = LABEL_WIDTH + CELL_WIDTH * i;
+            text(
+                &mut svg,
+                x + 4,
+                0,
+                &format!("H{} R{}", column.height, column.round),
+            );
+
+            let (fill, label) = match column.proposal.as_ref() {
+                Some(p) if byzantine.contains(&p.content) => (BYZANTINE, short(&p.content)),
+                Some(p) => (NORMAL, short(&p.content)),
+                None => (OFFLINE, "-".to_string()),
+            };
+            let error = self.is_error(column, Step::Propose);
+            cell(&mut svg, x, 1, fill, &label, error);
+            for (j, p) in column.prevotes.iter().enumerate() {
+                let (fill, label) = vote_cell(p);
+                let error = self.is_error(column, Step::Prevote);
+                cell(&mut svg, x, 2 + j, fill, &label, error);
+            }
+            for (j, p) in column.precommits.iter().enumerate() {
+                let (fill, label) = vote_cell(p);
+                let error = self.is_error(column, Step::Precommit);
+                cell(&mut svg, x, 2 + n + j, fill, &label, error);
+            }
+            let (fill, label) = match column.lock.as_ref() {
+                Some(LockChange::Lock(p)) => (NORMAL, format!("lock {}", short(p))),
+                Some(LockChange::Unlock) => (NIL, "unlock".to_string()),
+                None => (OFFLINE, String::new()),
+            };
+            cell(&mut svg, x, 2 + 2 * n, fill, &label, false);
+            let (fill, label) = match column.commit.as_ref() {
+                Some(p) => (NORMAL, short(p)),
+                None => (OFFLINE, String::new()),
+            };
+            let error = self.is_error(column, Step::Commit);
+            cell(&mut svg, x, 3 + 2 * n, fill, &label, error);
+        }
+
+        // legend
+        let legend = [
+            (NORMAL, "normal"),
+            (BYZANTINE, "byzantine"),
+            (NIL, "nil"),
+            (OFFLINE, "offline"),
+        ];
+        for (i, (fill, label)) in legend.iter().enumerate() {
+            cell(&mut svg, LABEL_WIDTH * i, rows, fill, label, false);
+        }
+        if self.error.is_some() {
+            cell(
+                &mut svg,
+                LABEL_WIDTH * legend.len(),
+                rows,
+                OFFLINE,
+                "error",
+                true,
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// A function to render the timeline as a self-contained HTML page, with the error
+    /// message if highlighted.
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        let _ = writeln!(html, "<!DOCTYPE html>");
+        let _ = writeln!(html, r#"<html><head><meta charset="utf-8">"#);
+        let _ = writeln!(html, "<title>Run {}</title></head><body>", self.run_id);
+        let _ = writeln!(html, "<h1>Run {}</h1>", self.run_id);
+        if let Some(msg) = self.error.as_ref() {
+            let _ = write!(html, "<p>{}", escape(msg));
+            if let Some((height, round, step)) = self.error_at {
+                let _ = write!(html, " at height {}, round {}", height, round);
+                if let Some(step) = step {
+                    let _ = write!(html, ", step {:?}", step);
+                }
+            }
+            let _ = writeln!(html, "</p>");
+        }
+        html.push_str(&self.to_svg());
+        html.push_str("</body></html>\n");
+        html
+    }
+}
+
+pub(crate) fn short(hash: &[u8]) -> String {
+    hash.iter().take(3).map(|b| format!("{:02x}", b)).collect()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn text(svg: &mut String, x: usize, row: usize, label: &str) {
+    if label.is_empty() {
+        return;
+    }
+    let _ = writeln!(
+        svg,
+        r#"<text x="{}" y="{}">{}</text>"#,
+        x,
+        ROW_HEIGHT * row + 15,
+        escape(label)
+    );
+}
+
+fn cell(svg: &mut String, x: usize, row: usize, fill: &str, label: &str, error: bool) {
+    let (stroke, stroke_width) = if error { (ERROR, 3) } else { ("#999999", 1) };
+    let _ = writeln!(
+        svg,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" stroke="{}" stroke-width="{}"/>"#,
+        x + 1,
+        ROW_HEIGHT * row + 1,
+        CELL_WIDTH - 2,
+        ROW_HEIGHT - 2,
+        fill,
+        stroke,
+        stroke_width
+    );
+    text(svg, x + 4, row, label);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testkit::{Mutation, ReferenceNode};
+    use crate::whitebox::{
+        actuator::Actuator, collection::storage::remove_db, correctness::test_case::lock_proposal,
+    };
+    use rand::random;
+
+    #[test]
+    fn test_timeline() {
+        let path = std::env::temp_dir().join(format!("bft_timeline_{}.db", random::<u64>()));
+        let path = path.to_str().unwrap();
+        let authority_list: Vec<Vec<u8>> = (0..4).map(|i| vec![i]).collect();
+        let node = ReferenceNode::with_mutation(authority_list.clone(), Mutation::DoubleVote);
+        let mut actuator = Actuator::new(node, 0, 0, authority_list.clone(), path).unwrap();
+        let err = actuator.proc_test(lock_proposal()).unwrap_err();
+        let ctx = err.context().unwrap().clone();
+
+        let reader = TraceReader::open(path).unwrap();
+        let mut timeline = Timeline::load(&reader, actuator.run_id(), authority_list).unwrap();
+        assert!(timeline
+            .columns
+            .iter()
+            .any(|c| c.height == ctx.height && c.round == ctx.round));
+        timeline.highlight(&err);
+        let html = timeline.to_html();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains(&format!("H{} R{}", ctx.height, ctx.round)));
+        assert!(html.contains(ERROR));
+        remove_db(path);
+    }
+}